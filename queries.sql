@@ -2,18 +2,67 @@ create table videos (
   id uuid default uuid_generate_v4() primary key,
   title text not null,
   description text,
-  video_url text not null,
-  thumbnail_url text,
+  -- Object paths in storage. Clients get signed app URLs built from them, so no
+  -- storage URL is ever stored.
+  source_path text,
+  video_path text,
+  thumbnail_path text,
+  stream_path text,
+  dash_path text,
+  encoding_profile text,
+  container text,
+  duration double precision,
+  video_codec text,
+  audio_codec text,
+  width integer,
+  height integer,
+  rotation integer,
+  frame_rate double precision,
+  audio_channels integer,
+  bit_rate bigint,
+  user_id uuid references auth.users (id),
+  status text not null default 'uploaded' check (status in ('pending', 'uploaded', 'processing', 'ready', 'failed')),
   created_at timestamp with time zone default timezone('utc'::text, now()) not null,
   likes integer default 0,
   views integer default 0
 );
 
+-- Upgrading a database that still stores storage URLs on videos:
+--
+-- alter table videos drop column video_url, drop column thumbnail_url,
+--   drop column stream_url, drop column dash_url;
+
+-- Requests made for a user carry their access token, so these policies apply.
+-- Background processing uses the service key and bypasses them.
+alter table videos enable row level security;
+
+create policy "Anyone can read videos" on videos
+  for select using (true);
+
+create policy "Publishers can add their own videos" on videos
+  for insert with check (
+    auth.uid() = user_id
+    and auth.jwt() -> 'app_metadata' ->> 'role' in ('admin', 'creator')
+  );
+
+create policy "Owners and admins can update videos" on videos
+  for update using (
+    auth.uid() = user_id or auth.jwt() -> 'app_metadata' ->> 'role' = 'admin'
+  );
+
+create policy "Owners and admins can delete videos" on videos
+  for delete using (
+    auth.uid() = user_id or auth.jwt() -> 'app_metadata' ->> 'role' = 'admin'
+  );
+
+-- Called by the service after deduplicating, so clients can't inflate counts directly
 create function increment_views(video_id uuid) returns void as $$
 begin
-  update videos set views = views + 1 where id = video_id;
+  update videos set views = views + 1 where videos.id = video_id;
 end;
-$$ language plpgsql;
+$$ language plpgsql security definer set search_path = public;
+
+revoke execute on function increment_views(uuid) from public, anon, authenticated;
 
 create function toggle_like(video_id uuid) returns void as $$
 begin
@@ -0,0 +1,68 @@
+-- Run after queries.sql and queries/shows.sql
+create table episodes(
+    id uuid default uuid_generate_v4() primary key,
+    show_id uuid not null references shows (id) on delete cascade,
+    -- Deleting a video removes its episode
+    video_id uuid not null unique references videos (id) on delete cascade,
+    season integer not null check (season >= 1),
+    episode_number integer not null check (episode_number >= 1),
+    title text not null,
+    synopsis text,
+    -- Seconds, copied from the video when it is attached
+    duration double precision,
+    status text not null default 'draft' check (status in ('draft', 'published')),
+    published_at timestamp,
+    created_at timestamp not null default now(),
+    updated_at timestamp not null default now(),
+    -- Checked at commit so reordering can swap numbers within one transaction
+    unique (show_id, season, episode_number) deferrable initially deferred
+);
+
+create index idx_episodes_show on episodes(show_id, season, episode_number);
+
+alter table episodes enable row level security;
+
+create policy "Anyone can read published episodes" on episodes
+  for select using (status = 'published');
+
+create policy "Publishers can manage episodes" on episodes
+  for all using (auth.jwt() -> 'app_metadata' ->> 'role' in ('admin', 'creator'))
+  with check (auth.jwt() -> 'app_metadata' ->> 'role' in ('admin', 'creator'));
+
+-- shows.episode_count counts published episodes and is only written here.
+-- Runs as the definer so it can update shows whatever the caller's policies.
+create or replace function refresh_episode_count() returns trigger as $$
+begin
+    update shows
+    set episode_count = (
+        select count(*) from episodes
+        where show_id = shows.id and status = 'published'
+    )
+    where id in (
+        select show_id from (select new.show_id union select old.show_id) as changed(show_id)
+        where show_id is not null
+    );
+    return null;
+end;
+$$ language plpgsql security definer set search_path = public;
+
+create trigger episodes_refresh_episode_count
+after insert or update or delete on episodes
+for each row execute function refresh_episode_count();
+
+-- Move episodes to new seasons and numbers in one statement, so numbers can be swapped.
+-- Episodes of other shows are left alone.
+create or replace function reorder_episodes(show_param uuid, positions jsonb)
+returns setof episodes as $$
+begin
+    update episodes e
+    set season = p.season, episode_number = p.episode_number, updated_at = now()
+    from jsonb_to_recordset(positions) as p(id uuid, season integer, episode_number integer)
+    where e.id = p.id and e.show_id = show_param;
+
+    return query
+    select * from episodes
+    where show_id = show_param
+    order by season, episode_number;
+end;
+$$ language plpgsql;
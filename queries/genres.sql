@@ -0,0 +1,63 @@
+-- Run before queries/shows.sql
+create table genres(
+    -- Stored in shows.genres, so it can't change once created
+    slug text primary key check (slug ~ '^[a-z0-9]+(-[a-z0-9]+)*$'),
+    display_name text not null check (length(trim(display_name)) > 0),
+    -- Locale code to display name, such as {"es": "Venganza"}
+    localized_names jsonb not null default '{}' check (jsonb_typeof(localized_names) = 'object'),
+    sort_order integer not null default 0,
+    -- Inactive genres stay on existing shows but can't be given to new ones
+    active boolean not null default true,
+    created_at timestamp not null default now(),
+    updated_at timestamp not null default now()
+);
+
+create index idx_genres_sort on genres(sort_order, slug);
+
+alter table genres enable row level security;
+
+create policy "Anyone can read genres" on genres
+  for select using (true);
+
+create policy "Admins can manage genres" on genres
+  for all using (auth.jwt() -> 'app_metadata' ->> 'role' = 'admin')
+  with check (auth.jwt() -> 'app_metadata' ->> 'role' = 'admin');
+
+-- A genre can't be deleted while a show carries it. check_show_genres in
+-- queries/shows.sql locks the genres a show is saved with, so a delete and a
+-- show write that race wait for each other instead of both succeeding.
+create or replace function check_genre_unused() returns trigger as $$
+begin
+    if exists (select 1 from shows where old.slug = any(genres)) then
+        raise exception 'Genre % is used by shows', old.slug using errcode = 'foreign_key_violation';
+    end if;
+    return old;
+end;
+$$ language plpgsql;
+
+create trigger genres_check_unused
+before delete on genres
+for each row execute function check_genre_unused();
+
+insert into genres (slug, display_name, sort_order) values
+    ('revenge', 'Revenge', 10),
+    ('billionaire', 'Billionaire', 20),
+    ('asian', 'Asian', 30),
+    ('romance', 'Romance', 40);
+
+-- Upgrading a database whose shows still have the single `genre` column:
+--
+-- alter table shows drop constraint shows_genre_check;
+-- alter table shows add column genres text[] not null default '{}';
+-- alter table shows add column tags text[] not null default '{}';
+-- update shows set genres = array[replace(lower(genre), 'billionare', 'billionaire')];
+-- drop function get_shows_by_genre(text);
+-- drop index idx_shows_genre;
+-- drop index idx_shows_genre_rating;
+-- alter table shows drop column genre;
+--
+-- then run the index, trigger and function definitions from queries/shows.sql.
+
+-- Upgrading a database created before genre deletes were checked by a trigger:
+-- run the check_genre_unused function and genres_check_unused trigger above,
+-- then the check_show_genres function from queries/shows.sql.
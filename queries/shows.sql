@@ -1,31 +1,66 @@
 create table shows(
     id uuid default uuid_generate_v4() primary key,
-    title text not null,
+    title text not null check (length(trim(title)) > 0),
     description text not null,
     release_date date not null,
     thumbnail_url text not null,
-    episode_count integer not null,
-    genre text not null check (genre in ('Revenge', 'Billionare', 'Asian', 'Romance')),
-    rating float not null,
-    status text not null,
+    -- Published episodes, kept up to date by a trigger in episodes.sql
+    episode_count integer not null default 0 check (episode_count >= 0),
+    -- Slugs from the genres table, checked by the trigger below
+    genres text[] not null default '{}',
+    -- Free-form lowercase labels
+    tags text[] not null default '{}',
+    rating float not null check (rating between 0 and 5),
+    status text not null check (status in ('announced', 'ongoing', 'completed', 'hiatus', 'cancelled')),
     created_at timestamp not null default now(),
     updated_at timestamp not null default now()
 );
 
 -- Enable Row Level Security (RLS) on shows table
 alter table shows enable row level security;
--- Create an index on the genre column for faster querying
-create index idx_shows_genre on shows(genre);
+
+create policy "Anyone can read shows" on shows
+  for select using (true);
+
+create policy "Publishers can manage shows" on shows
+  for all using (auth.jwt() -> 'app_metadata' ->> 'role' in ('admin', 'creator'))
+  with check (auth.jwt() -> 'app_metadata' ->> 'role' in ('admin', 'creator'));
+
+-- GIN indexes serve both `genre = any(genres)` and PostgREST's `cs` filter
+create index idx_shows_genres on shows using gin(genres);
+create index idx_shows_tags on shows using gin(tags);
 create index idx_shows_rating on shows(rating);
 
-create index idx_shows_genre_rating on shows(genre, rating);
+-- Every genre a show carries must exist. The API also requires them to be active.
+create or replace function check_show_genres() returns trigger as $$
+declare
+    unknown text;
+begin
+    -- Hold the genres until this write commits so they can't be deleted under it
+    perform 1 from genres where slug = any(new.genres) for key share;
+
+    select g into unknown
+    from unnest(new.genres) as g
+    where not exists (select 1 from genres where slug = g)
+    limit 1;
+
+    if unknown is not null then
+        raise exception 'Unknown genre: %', unknown using errcode = 'foreign_key_violation';
+    end if;
+    return new;
+end;
+$$ language plpgsql;
+
+create trigger shows_check_genres
+before insert or update of genres on shows
+for each row execute function check_show_genres();
 
 create or replace function get_shows_by_genre(genre_param text)
 returns setof shows as $$
 begin
     return query
     select * from shows
-    where genre = genre_param
+    where genre_param = any(genres)
     order by rating desc, release_date desc;
 end;
-$$ language plpgsql;
+$$ language plpgsql stable;
@@ -0,0 +1,239 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use axum::{
+    extract::{FromRequestParts, OptionalFromRequestParts},
+    http::{header, request::Parts},
+};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header,
+    jwk::{AlgorithmParameters, JwkSet},
+};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::{error::AppError, models::AppState};
+
+pub const ROLE_ADMIN: &str = "admin";
+pub const ROLE_CREATOR: &str = "creator";
+/// Roles allowed to upload videos and create shows
+pub const PUBLISHER_ROLES: &[&str] = &[ROLE_ADMIN, ROLE_CREATOR];
+
+// Clock skew tolerated when checking `exp` and `nbf`
+const LEEWAY_SECS: u64 = 30;
+
+/// The user a request's Supabase access token belongs to
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    // `sub`, the user's id in `auth.users`
+    pub id: String,
+    // `app_metadata.role`, or empty when the token has none
+    pub role: String,
+    pub claims: Value,
+    // The bearer token itself, forwarded to PostgREST so RLS sees the same user
+    pub access_token: String,
+}
+
+impl AuthUser {
+    pub fn has_role(&self, roles: &[&str]) -> bool {
+        roles.contains(&self.role.as_str())
+    }
+
+    /// Fail with 403 unless the user has one of `roles`
+    pub fn require_role(&self, roles: &[&str]) -> Result<(), AppError> {
+        if self.has_role(roles) {
+            return Ok(());
+        }
+
+        Err(AppError::Forbidden(format!(
+            "Requires one of the roles: {}",
+            roles.join(", ")
+        )))
+    }
+
+    /// Whether the user may act on something `owner` created. Admins may act on anything.
+    pub fn can_manage(&self, owner: Option<&str>) -> bool {
+        self.role == ROLE_ADMIN || owner == Some(self.id.as_str())
+    }
+}
+
+struct JwkKey {
+    key: DecodingKey,
+    algorithms: Vec<Algorithm>,
+}
+
+/// Checks Supabase-issued access tokens. Projects sign with the shared HS256 JWT
+/// secret, asymmetric keys published as a JWKS, or both while migrating.
+pub struct JwtVerifier {
+    secret: Option<DecodingKey>,
+    // Keyed by `kid`
+    jwks: HashMap<String, JwkKey>,
+    audience: String,
+}
+
+impl JwtVerifier {
+    pub fn new(
+        secret: Option<&str>,
+        jwks: Option<&str>,
+        audience: impl Into<String>,
+    ) -> Result<Self, String> {
+        let jwks = match jwks {
+            Some(json) => {
+                let set: JwkSet =
+                    serde_json::from_str(json).map_err(|e| format!("Invalid JWKS: {}", e))?;
+                set.keys
+                    .iter()
+                    .filter_map(|jwk| {
+                        let kid = jwk.common.key_id.clone()?;
+                        let key = match DecodingKey::from_jwk(jwk) {
+                            Ok(key) => key,
+                            Err(e) => return Some(Err(format!("Invalid JWK {}: {}", kid, e))),
+                        };
+                        let algorithms = match &jwk.algorithm {
+                            AlgorithmParameters::RSA(_) => vec![
+                                Algorithm::RS256,
+                                Algorithm::RS384,
+                                Algorithm::RS512,
+                                Algorithm::PS256,
+                                Algorithm::PS384,
+                                Algorithm::PS512,
+                            ],
+                            AlgorithmParameters::EllipticCurve(_) => {
+                                vec![Algorithm::ES256, Algorithm::ES384]
+                            }
+                            AlgorithmParameters::OctetKeyPair(_) => vec![Algorithm::EdDSA],
+                            AlgorithmParameters::OctetKey(_) => {
+                                vec![Algorithm::HS256, Algorithm::HS384, Algorithm::HS512]
+                            }
+                        };
+                        Some(Ok((kid, JwkKey { key, algorithms })))
+                    })
+                    .collect::<Result<HashMap<_, _>, _>>()?
+            }
+            None => HashMap::new(),
+        };
+
+        if secret.is_none() && jwks.is_empty() {
+            return Err("A JWT secret or at least one JWK with a kid is required".to_string());
+        }
+
+        Ok(Self {
+            secret: secret.map(|s| DecodingKey::from_secret(s.as_bytes())),
+            jwks,
+            audience: audience.into(),
+        })
+    }
+
+    pub fn from_jwks_file(
+        secret: Option<&str>,
+        path: &Path,
+        audience: impl Into<String>,
+    ) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::new(secret, Some(&json), audience)
+    }
+
+    /// Check a token's signature, expiry and audience
+    pub fn verify(&self, token: &str) -> Result<AuthUser, AppError> {
+        let header = decode_header(token).map_err(|_| unauthorized("Malformed access token"))?;
+
+        let (key, algorithms) = match &header.kid {
+            Some(kid) if self.jwks.contains_key(kid) => {
+                let jwk = &self.jwks[kid];
+                (&jwk.key, jwk.algorithms.clone())
+            }
+            _ => match &self.secret {
+                Some(secret) => (secret, vec![Algorithm::HS256]),
+                None => return Err(unauthorized("Access token signed with an unknown key")),
+            },
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.algorithms = algorithms;
+        validation.set_audience(&[&self.audience]);
+        validation.set_required_spec_claims(&["exp", "sub"]);
+        validation.leeway = LEEWAY_SECS;
+
+        let claims = decode::<Value>(token, key, &validation)
+            .map_err(|e| match e.kind() {
+                jsonwebtoken::errors::ErrorKind::ExpiredSignature => {
+                    unauthorized("Access token has expired")
+                }
+                _ => {
+                    warn!("Rejected access token: {}", e);
+                    unauthorized("Invalid access token")
+                }
+            })?
+            .claims;
+
+        let id = claims["sub"]
+            .as_str()
+            .ok_or_else(|| unauthorized("Access token has no subject"))?
+            .to_string();
+        // The RLS policies only trust `app_metadata`, which users can't edit. The
+        // top-level `role` is the Postgres role, such as `authenticated`.
+        let role = claims["app_metadata"]["role"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+
+        Ok(AuthUser {
+            id,
+            role,
+            claims,
+            access_token: token.to_string(),
+        })
+    }
+}
+
+/// The token from `Authorization: Bearer ...`, if the header is present
+fn bearer_token(parts: &Parts) -> Option<Result<&str, AppError>> {
+    let value = parts.headers.get(header::AUTHORIZATION)?;
+    Some(
+        value
+            .to_str()
+            .ok()
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| unauthorized("Authorization must be a Bearer token")),
+    )
+}
+
+fn authenticate(state: &AppState, token: &str) -> Result<AuthUser, AppError> {
+    match &state.auth {
+        Some(verifier) => verifier.verify(token),
+        None => Err(unauthorized("Authentication is not configured")),
+    }
+}
+
+fn unauthorized(message: &str) -> AppError {
+    AppError::Unauthorized(message.to_string())
+}
+
+impl FromRequestParts<Arc<AppState>> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(parts).unwrap_or_else(|| Err(unauthorized("Sign in required")))?;
+        authenticate(state, token)
+    }
+}
+
+/// Anonymous requests get `None`, but a token that is present must be valid
+impl OptionalFromRequestParts<Arc<AppState>> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        match bearer_token(parts) {
+            Some(token) => authenticate(state, token?).map(Some),
+            None => Ok(None),
+        }
+    }
+}
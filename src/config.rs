@@ -1,17 +1,238 @@
-use crate::models::AppState;
+use crate::{
+    auth::JwtVerifier,
+    encoding::{EncodingProfile, EncodingProfiles, ManifestFormat, Rendition},
+    jobs::JobQueue,
+    models::AppState,
+    playback::PlaybackSigner,
+    storage::{LocalStorage, MemoryStorage, S3Config, S3Storage, StorageBackend, SupabaseStorage},
+    tus::TusStore,
+    views::ViewTracker,
+};
 use dotenv::dotenv;
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tracing::warn;
 
-pub const VIDEO_BUCKET: &str = "videos";
+pub const DEFAULT_VIDEO_BUCKET: &str = "videos";
+pub const DEFAULT_LOCAL_STORAGE_DIR: &str = "storage";
+pub const DEFAULT_S3_REGION: &str = "us-east-1";
+pub const DEFAULT_PUBLIC_BASE_URL: &str = "http://localhost:3000";
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+pub const MAX_PAGE_SIZE: usize = 100;
+pub const DEFAULT_MAX_UPLOAD_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+pub const DEFAULT_TUS_UPLOAD_TTL_SECS: u64 = 24 * 60 * 60;
+pub const DEFAULT_TRANSCODE_WORKERS: usize = 2;
+pub const DEFAULT_TRANSCODE_QUEUE_CAPACITY: usize = 32;
+// Supabase signed upload URLs are valid for a fixed two hours
+pub const SIGNED_UPLOAD_URL_TTL_SECS: u64 = 2 * 60 * 60;
+// Headroom on top of the file size for multipart boundaries and text fields
+pub const MULTIPART_OVERHEAD_BYTES: u64 = 1024 * 1024;
+pub const DEFAULT_SEGMENT_SECONDS: u32 = 6;
+pub const DEFAULT_MAX_VIDEO_DURATION_SECS: u64 = 4 * 60 * 60;
+pub const DEFAULT_ENCODING_PROFILE: &str = "default";
+pub const DEFAULT_PLAYBACK_URL_TTL_SECS: u64 = 60 * 60;
+pub const DEFAULT_MIN_VIEW_WATCH_SECS: f64 = 30.0;
+pub const DEFAULT_VIEW_DEDUP_WINDOW_SECS: u64 = 30 * 60;
+pub const DEFAULT_MAX_ANONYMOUS_VIEWS_PER_IP: usize = 5;
+// Supabase issues access tokens for this audience
+pub const DEFAULT_JWT_AUDIENCE: &str = "authenticated";
 
 pub fn load_config() -> Arc<AppState> {
     // Load environment variables
     dotenv().ok();
 
+    let tus_upload_dir = std::env::var("TUS_UPLOAD_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| std::env::temp_dir().join("tus-uploads"));
+    // Unfinished uploads are removed once they haven't been written to for this long
+    let tus_upload_ttl = std::env::var("TUS_UPLOAD_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_TUS_UPLOAD_TTL_SECS);
+    let tus = TusStore::new(tus_upload_dir, Duration::from_secs(tus_upload_ttl))
+        .expect("TUS_UPLOAD_DIR must be writable");
+
+    let transcode_workers = std::env::var("TRANSCODE_WORKERS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_TRANSCODE_WORKERS);
+    let transcode_queue_capacity = std::env::var("TRANSCODE_QUEUE_CAPACITY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_TRANSCODE_QUEUE_CAPACITY);
+
+    let ladder = match std::env::var("STREAM_LADDER") {
+        Ok(spec) => Rendition::parse_ladder(&spec)
+            .unwrap_or_else(|e| panic!("STREAM_LADDER is invalid: {}", e)),
+        Err(_) => Rendition::default_ladder(),
+    };
+    let segment_seconds = std::env::var("STREAM_SEGMENT_SECONDS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_SEGMENT_SECONDS);
+    let manifests = match std::env::var("STREAM_MANIFESTS") {
+        Ok(spec) => ManifestFormat::parse_list(&spec)
+            .unwrap_or_else(|e| panic!("STREAM_MANIFESTS is invalid: {}", e)),
+        Err(_) => vec![ManifestFormat::Hls, ManifestFormat::Dash],
+    };
+    let default_profile = std::env::var("DEFAULT_ENCODING_PROFILE")
+        .unwrap_or_else(|_| DEFAULT_ENCODING_PROFILE.to_string());
+    let standard_profile =
+        EncodingProfile::standard(&default_profile, ladder, segment_seconds, manifests);
+    // Profiles clients can choose from; without a file only the standard one exists
+    let encoding_profiles = match std::env::var("ENCODING_PROFILES_FILE") {
+        Ok(path) => {
+            EncodingProfiles::load(&PathBuf::from(path), &standard_profile, &default_profile)
+                .unwrap_or_else(|e| panic!("ENCODING_PROFILES_FILE is invalid: {}", e))
+        }
+        Err(_) => EncodingProfiles::single(standard_profile),
+    };
+
+    let supabase_url = std::env::var("SUPABASE_URL").expect("SUPABASE_URL must be set");
+    let supabase_api_key = std::env::var("SUPABASE_API_KEY").expect("SUPABASE_API_KEY must be set");
+    // Anonymous requests must never fall back to the service key, which bypasses RLS
+    let supabase_anon_key =
+        std::env::var("SUPABASE_ANON_KEY").expect("SUPABASE_ANON_KEY must be set");
+
+    // Where objects are stored; local and memory storage are served by this app
+    let public_base_url = std::env::var("PUBLIC_BASE_URL")
+        .unwrap_or_else(|_| DEFAULT_PUBLIC_BASE_URL.to_string())
+        .trim_end_matches('/')
+        .to_string();
+    let storage: Arc<dyn StorageBackend> = match std::env::var("STORAGE_BACKEND")
+        .unwrap_or_else(|_| "supabase".to_string())
+        .as_str()
+    {
+        "supabase" => Arc::new(SupabaseStorage::new(
+            &supabase_url,
+            &supabase_api_key,
+            std::env::var("SUPABASE_BUCKET").unwrap_or_else(|_| DEFAULT_VIDEO_BUCKET.to_string()),
+        )),
+        "local" => {
+            let dir = std::env::var("LOCAL_STORAGE_DIR")
+                .unwrap_or_else(|_| DEFAULT_LOCAL_STORAGE_DIR.to_string());
+            Arc::new(
+                LocalStorage::new(dir, &public_base_url)
+                    .expect("LOCAL_STORAGE_DIR must be writable"),
+            )
+        }
+        "memory" => Arc::new(MemoryStorage::new(&public_base_url)),
+        "s3" => {
+            let region =
+                std::env::var("S3_REGION").unwrap_or_else(|_| DEFAULT_S3_REGION.to_string());
+            let config = S3Config {
+                bucket: std::env::var("S3_BUCKET").expect("S3_BUCKET must be set"),
+                endpoint: std::env::var("S3_ENDPOINT")
+                    .unwrap_or_else(|_| format!("https://s3.{}.amazonaws.com", region)),
+                region,
+                access_key_id: std::env::var("S3_ACCESS_KEY_ID")
+                    .expect("S3_ACCESS_KEY_ID must be set"),
+                secret_access_key: std::env::var("S3_SECRET_ACCESS_KEY")
+                    .expect("S3_SECRET_ACCESS_KEY must be set"),
+                // MinIO and most self-hosted stores need path-style addressing
+                path_style: std::env::var("S3_FORCE_PATH_STYLE")
+                    .is_ok_and(|v| matches!(v.as_str(), "1" | "true")),
+                public_url: std::env::var("S3_PUBLIC_URL").ok(),
+            };
+            Arc::new(S3Storage::new(config).unwrap_or_else(|e| panic!("{}", e)))
+        }
+        other => panic!(
+            "STORAGE_BACKEND must be supabase, s3, local or memory, not {:?}",
+            other
+        ),
+    };
+
+    // Keys that Supabase access tokens are checked against
+    let jwt_secret = std::env::var("SUPABASE_JWT_SECRET")
+        .ok()
+        .filter(|s| !s.is_empty());
+    let jwt_audience =
+        std::env::var("SUPABASE_JWT_AUDIENCE").unwrap_or_else(|_| DEFAULT_JWT_AUDIENCE.to_string());
+    let auth = match (
+        std::env::var("SUPABASE_JWKS_FILE"),
+        std::env::var("SUPABASE_JWKS"),
+    ) {
+        (Ok(path), _) => Some(
+            JwtVerifier::from_jwks_file(jwt_secret.as_deref(), &PathBuf::from(path), jwt_audience)
+                .unwrap_or_else(|e| panic!("SUPABASE_JWKS_FILE is invalid: {}", e)),
+        ),
+        (_, Ok(jwks)) => Some(
+            JwtVerifier::new(jwt_secret.as_deref(), Some(&jwks), jwt_audience)
+                .unwrap_or_else(|e| panic!("SUPABASE_JWKS is invalid: {}", e)),
+        ),
+        (_, _) => jwt_secret.as_deref().map(|secret| {
+            JwtVerifier::new(Some(secret), None, jwt_audience)
+                .expect("a JWT secret alone is always valid")
+        }),
+    };
+    if auth.is_none() {
+        warn!(
+            "No SUPABASE_JWT_SECRET or JWKS configured, authenticated endpoints will refuse every request"
+        );
+    }
+
+    // Without a secret playback URLs aren't signed and streams are open to anyone
+    let playback = std::env::var("PLAYBACK_SIGNING_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty())
+        .map(|secret| {
+            let ttl = std::env::var("PLAYBACK_URL_TTL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(DEFAULT_PLAYBACK_URL_TTL_SECS);
+            let bind_ip =
+                std::env::var("PLAYBACK_BIND_IP").is_ok_and(|v| matches!(v.as_str(), "1" | "true"));
+            Arc::new(PlaybackSigner::new(
+                secret,
+                Duration::from_secs(ttl),
+                bind_ip,
+            ))
+        });
+
+    // A view counts once it has been watched this long, once per viewer per window,
+    // and only a few times per window for anonymous viewers sharing an address
+    let views = ViewTracker::new(
+        std::env::var("MIN_VIEW_WATCH_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|v: &f64| v.is_finite() && *v >= 0.0)
+            .unwrap_or(DEFAULT_MIN_VIEW_WATCH_SECS),
+        Duration::from_secs(
+            std::env::var("VIEW_DEDUP_WINDOW_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_VIEW_DEDUP_WINDOW_SECS),
+        ),
+        std::env::var("MAX_ANONYMOUS_VIEWS_PER_IP")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_ANONYMOUS_VIEWS_PER_IP),
+    );
+
     // Initialize application state
     Arc::new(AppState {
-        supabase_url: std::env::var("SUPABASE_URL").expect("SUPABASE_URL must be set"),
-        supabase_api_key: std::env::var("SUPABASE_API_KEY").expect("SUPABASE_API_KEY must be set"),
-        supabase_bucket: std::env::var("SUPABASE_BUCKET").unwrap_or_else(|_| "videos".to_string()),
+        supabase_url,
+        supabase_api_key,
+        supabase_anon_key,
+        storage,
+        auth: auth.map(Arc::new),
+        public_base_url,
+        playback,
+        trust_forwarded_for: std::env::var("TRUST_FORWARDED_FOR")
+            .is_ok_and(|v| matches!(v.as_str(), "1" | "true")),
+        max_upload_bytes: std::env::var("MAX_UPLOAD_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_UPLOAD_BYTES),
+        max_video_duration_secs: std::env::var("MAX_VIDEO_DURATION_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_VIDEO_DURATION_SECS),
+        tus: Arc::new(tus),
+        jobs: Arc::new(JobQueue::new(transcode_workers, transcode_queue_capacity)),
+        encoding_profiles: Arc::new(encoding_profiles),
+        views: Arc::new(views),
     })
 }
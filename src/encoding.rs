@@ -0,0 +1,448 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+
+/// Adaptive streaming manifests that can be written for the packaged segments
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestFormat {
+    Hls,
+    Dash,
+}
+
+impl ManifestFormat {
+    /// Parse a comma separated list such as `hls,dash`
+    pub fn parse_list(spec: &str) -> Result<Vec<ManifestFormat>, String> {
+        let mut formats = Vec::new();
+        for name in spec.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let format = match name.to_ascii_lowercase().as_str() {
+                "hls" => ManifestFormat::Hls,
+                "dash" => ManifestFormat::Dash,
+                _ => return Err(format!("Unknown manifest format {:?}", name)),
+            };
+            if !formats.contains(&format) {
+                formats.push(format);
+            }
+        }
+
+        if formats.is_empty() {
+            return Err("At least one manifest format is required".to_string());
+        }
+
+        Ok(formats)
+    }
+}
+
+/// Video encoders a profile can use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+    H264,
+    H265,
+    Vp9,
+    Av1,
+}
+
+impl VideoCodec {
+    /// The ffmpeg encoder used for this codec
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "libx264",
+            VideoCodec::H265 => "libx265",
+            VideoCodec::Vp9 => "libvpx-vp9",
+            VideoCodec::Av1 => "libsvtav1",
+        }
+    }
+
+    /// Encoder speed option for output stream `stream`, e.g. `-preset:v:0 medium`.
+    /// libvpx has no presets and takes a deadline (`good`, `best` or `realtime`) instead.
+    pub fn preset_args(&self, stream: &str, preset: &str) -> [String; 2] {
+        match self {
+            VideoCodec::Vp9 => [format!("-deadline{}", stream), preset.to_string()],
+            _ => [format!("-preset{}", stream), preset.to_string()],
+        }
+    }
+}
+
+/// Audio encoders a profile can use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioCodec {
+    Aac,
+    Opus,
+}
+
+impl AudioCodec {
+    /// The ffmpeg encoder used for this codec
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            AudioCodec::Aac => "aac",
+            AudioCodec::Opus => "libopus",
+        }
+    }
+}
+
+/// Container of the progressive download file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    Mp4,
+    Webm,
+}
+
+impl Container {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Webm => "webm",
+        }
+    }
+
+    /// Audio codec used when a profile doesn't pick one
+    pub fn default_audio_codec(&self) -> AudioCodec {
+        match self {
+            Container::Mp4 => AudioCodec::Aac,
+            Container::Webm => AudioCodec::Opus,
+        }
+    }
+
+    fn supports(&self, codec: VideoCodec) -> bool {
+        match self {
+            Container::Mp4 => true,
+            Container::Webm => matches!(codec, VideoCodec::Vp9 | VideoCodec::Av1),
+        }
+    }
+
+    fn supports_audio(&self, codec: AudioCodec) -> bool {
+        match self {
+            Container::Mp4 => true,
+            Container::Webm => codec == AudioCodec::Opus,
+        }
+    }
+}
+
+/// Quality target for the progressive download. Ladder renditions always use
+/// their own bitrates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateControl {
+    // Constant quality, lower is better
+    Crf(u32),
+    TargetBitrate { kbps: u32 },
+}
+
+/// A named set of encoding settings that uploads can ask for
+#[derive(Debug, Clone)]
+pub struct EncodingProfile {
+    pub name: String,
+    pub codec: VideoCodec,
+    pub rate_control: RateControl,
+    pub preset: String,
+    // Nothing is encoded taller than this
+    pub max_height: Option<u32>,
+    // Used for the download and every rendition
+    pub audio_codec: AudioCodec,
+    pub audio_bitrate_kbps: u32,
+    pub container: Container,
+    // Renditions to encode, lowest first; rungs above the source height are skipped
+    pub ladder: Vec<Rendition>,
+    pub segment_seconds: u32,
+    pub manifests: Vec<ManifestFormat>,
+}
+
+impl EncodingProfile {
+    /// Settings used when no profiles file is configured, matching what the
+    /// pipeline has always produced
+    pub fn standard(
+        name: &str,
+        ladder: Vec<Rendition>,
+        segment_seconds: u32,
+        manifests: Vec<ManifestFormat>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            codec: VideoCodec::H264,
+            rate_control: RateControl::Crf(23),
+            preset: "medium".to_string(),
+            max_height: None,
+            audio_codec: AudioCodec::Aac,
+            audio_bitrate_kbps: 128,
+            container: Container::Mp4,
+            ladder,
+            segment_seconds,
+            manifests,
+        }
+    }
+
+    /// Name of the progressive download file
+    pub fn video_file_name(&self) -> String {
+        format!("video.{}", self.container.extension())
+    }
+}
+
+/// One entry of the profiles file. Anything left out falls back to the
+/// standard profile.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileConfig {
+    codec: Option<VideoCodec>,
+    crf: Option<u32>,
+    bitrate_kbps: Option<u32>,
+    preset: Option<String>,
+    max_height: Option<u32>,
+    audio_codec: Option<AudioCodec>,
+    audio_bitrate_kbps: Option<u32>,
+    container: Option<Container>,
+    // Same format as `STREAM_LADDER`
+    ladder: Option<String>,
+    segment_seconds: Option<u32>,
+    manifests: Option<Vec<ManifestFormat>>,
+}
+
+impl ProfileConfig {
+    fn resolve(self, base: &EncodingProfile, name: &str) -> Result<EncodingProfile, String> {
+        let rate_control = match (self.crf, self.bitrate_kbps) {
+            (Some(_), Some(_)) => {
+                return Err(format!(
+                    "Profile {} sets both crf and bitrate_kbps, pick one",
+                    name
+                ));
+            }
+            (Some(crf), None) => RateControl::Crf(crf),
+            (None, Some(kbps)) => RateControl::TargetBitrate { kbps },
+            (None, None) => base.rate_control,
+        };
+
+        let codec = self.codec.unwrap_or(base.codec);
+        let container = self.container.unwrap_or(base.container);
+        if !container.supports(codec) {
+            return Err(format!(
+                "Profile {} can't put {:?} in a {:?} container",
+                name, codec, container
+            ));
+        }
+
+        // A profile that only changes the container gets that container's usual audio
+        let audio_codec = self.audio_codec.unwrap_or(if container == base.container {
+            base.audio_codec
+        } else {
+            container.default_audio_codec()
+        });
+        if !container.supports_audio(audio_codec) {
+            return Err(format!(
+                "Profile {} can't put {:?} audio in a {:?} container",
+                name, audio_codec, container
+            ));
+        }
+
+        let manifests = self.manifests.unwrap_or_else(|| base.manifests.clone());
+        if manifests.is_empty() {
+            return Err(format!("Profile {} has no manifests", name));
+        }
+
+        Ok(EncodingProfile {
+            name: name.to_string(),
+            codec,
+            rate_control,
+            preset: self.preset.unwrap_or_else(|| base.preset.clone()),
+            max_height: self.max_height.or(base.max_height),
+            audio_codec,
+            audio_bitrate_kbps: self.audio_bitrate_kbps.unwrap_or(base.audio_bitrate_kbps),
+            container,
+            ladder: match self.ladder {
+                Some(spec) => Rendition::parse_ladder(&spec)
+                    .map_err(|e| format!("Profile {}: {}", name, e))?,
+                None => base.ladder.clone(),
+            },
+            segment_seconds: self
+                .segment_seconds
+                .filter(|s| *s > 0)
+                .unwrap_or(base.segment_seconds),
+            manifests,
+        })
+    }
+}
+
+/// The profiles uploads may pick from. Only configured names are accepted.
+#[derive(Debug)]
+pub struct EncodingProfiles {
+    profiles: HashMap<String, EncodingProfile>,
+    default: String,
+}
+
+impl EncodingProfiles {
+    /// Just the standard profile, registered under `default`
+    pub fn single(profile: EncodingProfile) -> Self {
+        let default = profile.name.clone();
+        Self {
+            profiles: HashMap::from([(default.clone(), profile)]),
+            default,
+        }
+    }
+
+    /// Load a JSON object of profile name to settings, falling back to `base` for
+    /// anything a profile leaves out
+    pub fn load(path: &Path, base: &EncodingProfile, default: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let configs: HashMap<String, ProfileConfig> = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+        let profiles = configs
+            .into_iter()
+            .map(|(name, config)| Ok((name.clone(), config.resolve(base, &name)?)))
+            .collect::<Result<HashMap<_, _>, String>>()?;
+
+        if !profiles.contains_key(default) {
+            return Err(format!(
+                "Default encoding profile {} is not defined in {}",
+                default,
+                path.display()
+            ));
+        }
+
+        Ok(Self {
+            profiles,
+            default: default.to_string(),
+        })
+    }
+
+    /// Look up the profile an upload asked for, or the default when it didn't ask
+    pub fn get(&self, name: Option<&str>) -> Result<&EncodingProfile, AppError> {
+        let name = name
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .unwrap_or(&self.default);
+
+        self.profiles.get(name).ok_or_else(|| {
+            let mut allowed: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            allowed.sort_unstable();
+            AppError::Validation(format!(
+                "Unknown encoding profile {:?}, expected one of: {}",
+                name,
+                allowed.join(", ")
+            ))
+        })
+    }
+}
+
+/// One rung of the adaptive bitrate ladder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rendition {
+    pub name: String,
+    pub height: u32,
+    pub video_bitrate_kbps: u32,
+    pub audio_bitrate_kbps: u32,
+}
+
+impl Rendition {
+    pub fn new(height: u32, video_bitrate_kbps: u32, audio_bitrate_kbps: u32) -> Self {
+        Self {
+            name: format!("{}p", height),
+            height,
+            video_bitrate_kbps,
+            audio_bitrate_kbps,
+        }
+    }
+
+    pub fn default_ladder() -> Vec<Rendition> {
+        vec![
+            Rendition::new(240, 400, 64),
+            Rendition::new(360, 800, 96),
+            Rendition::new(540, 1800, 128),
+            Rendition::new(720, 3000, 128),
+            Rendition::new(1080, 6000, 192),
+        ]
+    }
+
+    /// Parse a ladder such as `240:400:64,360:800:96`, each rung being
+    /// `height:video_kbps[:audio_kbps]`
+    pub fn parse_ladder(spec: &str) -> Result<Vec<Rendition>, String> {
+        let mut ladder = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|rung| !rung.is_empty())
+            .map(|rung| {
+                let parts = rung
+                    .split(':')
+                    .map(|p| p.trim().parse::<u32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| format!("Invalid rendition {:?}", rung))?;
+
+                match parts[..] {
+                    [height, video] if height > 0 => Ok(Rendition::new(height, video, 128)),
+                    [height, video, audio] if height > 0 => {
+                        Ok(Rendition::new(height, video, audio))
+                    }
+                    _ => Err(format!("Invalid rendition {:?}", rung)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if ladder.is_empty() {
+            return Err("Rendition ladder is empty".to_string());
+        }
+
+        ladder.sort_by_key(|r| r.height);
+        Ok(ladder)
+    }
+
+    /// The rungs that don't upscale the source. A source smaller than every rung
+    /// gets a single rendition at its own height.
+    pub fn select(ladder: &[Rendition], source_height: u32) -> Vec<Rendition> {
+        let selected: Vec<Rendition> = ladder
+            .iter()
+            .filter(|r| r.height <= source_height)
+            .cloned()
+            .collect();
+
+        if !selected.is_empty() {
+            return selected;
+        }
+
+        match ladder.first() {
+            Some(lowest) => {
+                // libx264 needs even dimensions
+                let height = (source_height.max(2) / 2) * 2;
+                vec![Rendition::new(
+                    height,
+                    lowest.video_bitrate_kbps,
+                    lowest.audio_bitrate_kbps,
+                )]
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(config: &str) -> Result<EncodingProfile, String> {
+        let base = EncodingProfile::standard(
+            "default",
+            Rendition::default_ladder(),
+            4,
+            vec![ManifestFormat::Hls],
+        );
+        let config: ProfileConfig = serde_json::from_str(config).unwrap();
+        config.resolve(&base, "test")
+    }
+
+    #[test]
+    fn audio_codec_follows_the_container() {
+        assert_eq!(resolve("{}").unwrap().audio_codec, AudioCodec::Aac);
+        let webm = resolve(r#"{"codec": "vp9", "container": "webm"}"#).unwrap();
+        assert_eq!(webm.audio_codec, AudioCodec::Opus);
+        let mp4 = resolve(r#"{"audio_codec": "opus"}"#).unwrap();
+        assert_eq!(mp4.audio_codec, AudioCodec::Opus);
+    }
+
+    #[test]
+    fn rejects_aac_in_webm() {
+        assert!(resolve(r#"{"codec": "vp9", "container": "webm", "audio_codec": "aac"}"#).is_err());
+        assert!(resolve(r#"{"codec": "h264", "container": "webm"}"#).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+use std::fmt;
+
+use axum::{
+    Json,
+    extract::{
+        Request,
+        multipart::{MultipartError, MultipartRejection},
+        rejection::JsonRejection,
+    },
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use tracing::{Instrument, error, info_span};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+// Longest client-supplied request ID that is passed through rather than replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+// Axum's rejection messages are short; anything longer isn't one of them
+const MAX_REJECTION_BODY_LEN: usize = 16 * 1024;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Everything a handler, the storage backends or the processing pipeline can fail with.
+/// Client errors explain themselves; upstream and internal failures are logged in full
+/// and answered with a generic detail so service responses and paths never leak.
+#[derive(Debug)]
+pub enum AppError {
+    // 400, the request itself is wrong
+    Validation(String),
+    // 400, listing every invalid field of a request body at once
+    InvalidFields(Vec<FieldError>),
+    // 401, no usable access token
+    Unauthorized(String),
+    // 403, signed in but not allowed
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    // Any other client error with a more specific status, such as 413 or tus's 412
+    Rejected(StatusCode, String),
+    // Supabase Storage or S3 failed
+    UpstreamStorage(String),
+    // PostgREST failed
+    UpstreamDatabase(String),
+    // ffmpeg or ffprobe failed on input that passed validation
+    Transcode(String),
+    Internal(String),
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Validation(_) | AppError::InvalidFields(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Rejected(status, _) => *status,
+            AppError::UpstreamStorage(_) | AppError::UpstreamDatabase(_) => StatusCode::BAD_GATEWAY,
+            AppError::Transcode(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Stable machine-readable name for the kind of error
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Validation(_) | AppError::InvalidFields(_) => "validation",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::Rejected(..) => "rejected",
+            AppError::UpstreamStorage(_) => "upstream_storage",
+            AppError::UpstreamDatabase(_) => "upstream_database",
+            AppError::Transcode(_) => "transcode",
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    /// The full message, which for server-side errors may name upstream services
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::Validation(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message)
+            | AppError::Rejected(_, message)
+            | AppError::UpstreamStorage(message)
+            | AppError::UpstreamDatabase(message)
+            | AppError::Transcode(message)
+            | AppError::Internal(message) => message,
+            AppError::InvalidFields(_) => "One or more fields are invalid",
+        }
+    }
+
+    /// What the client is told
+    pub fn detail(&self) -> &str {
+        match self {
+            AppError::UpstreamStorage(_) => "The storage service request failed",
+            AppError::UpstreamDatabase(_) => "The database request failed",
+            AppError::Transcode(_) => "Video processing failed",
+            AppError::Internal(_) => "Internal server error",
+            _ => self.message(),
+        }
+    }
+
+    fn is_server_error(&self) -> bool {
+        self.status().is_server_error()
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+/// An RFC 7807 problem details body
+#[derive(Serialize)]
+struct Problem<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: &'a str,
+    status: u16,
+    detail: &'a str,
+    code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    // Extension member listing each invalid field
+    #[serde(skip_serializing_if = "<[FieldError]>::is_empty")]
+    errors: &'a [FieldError],
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let request_id = current_request_id();
+        if self.is_server_error() {
+            error!("{}", self);
+        }
+
+        let problem = Problem {
+            kind: "about:blank",
+            title: status.canonical_reason().unwrap_or_default(),
+            status: status.as_u16(),
+            detail: self.detail(),
+            code: self.code(),
+            request_id,
+            errors: match &self {
+                AppError::InvalidFields(errors) => errors,
+                _ => &[],
+            },
+        };
+
+        let mut response = (status, Json(problem)).into_response();
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+        response
+    }
+}
+
+/// One invalid field of a request body
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Collects every problem with a request body so they can be reported together
+#[derive(Debug, Default)]
+pub struct FieldErrors(Vec<FieldError>);
+
+impl FieldErrors {
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        });
+    }
+
+    /// Record a validation failure from `result` against `field`. Other errors are
+    /// passed through, since they aren't about the request.
+    pub fn check<T>(
+        &mut self,
+        field: &str,
+        result: Result<T, AppError>,
+    ) -> Result<Option<T>, AppError> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(AppError::Validation(message)) => {
+                self.add(field, message);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Fail with every recorded problem, if there are any
+    pub fn finish(self) -> Result<(), AppError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::InvalidFields(self.0))
+        }
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::Rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<MultipartRejection> for AppError {
+    fn from(rejection: MultipartRejection) -> Self {
+        AppError::Rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<MultipartError> for AppError {
+    fn from(err: MultipartError) -> Self {
+        AppError::Rejected(err.status(), err.body_text())
+    }
+}
+
+/// Extractor rejections such as a malformed path parameter or an oversized body are
+/// plain text from axum itself, so rewrite them as problem responses too
+async fn problem_from_rejection(response: Response) -> Response {
+    let is_plain_error = (response.status().is_client_error()
+        || response.status().is_server_error())
+        && response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/plain"));
+    if !is_plain_error {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let message = match axum::body::to_bytes(body, MAX_REJECTION_BODY_LEN).await {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(_) => String::new(),
+    };
+
+    let mut problem = AppError::Rejected(parts.status, message).into_response();
+    // Keep CORS and the like, but not headers describing the old body
+    for (name, value) in &parts.headers {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            problem.headers_mut().insert(name, value.clone());
+        }
+    }
+    problem
+}
+
+/// The ID of the request being handled, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Give every request an ID, reusing the caller's `X-Request-Id` when it sends a
+/// sensible one, so problem responses and log lines can be matched up
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| {
+            !v.is_empty()
+                && v.len() <= MAX_REQUEST_ID_LEN
+                && v.chars().all(|c| c.is_ascii_graphic())
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let span = info_span!("request", id = %id);
+    let mut response = REQUEST_ID
+        .scope(
+            id.clone(),
+            async {
+                let response = next.run(request).await;
+                problem_from_rejection(response).await
+            }
+            .instrument(span),
+        )
+        .await;
+
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
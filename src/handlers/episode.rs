@@ -0,0 +1,294 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use chrono::Utc;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::{
+    auth::{AuthUser, PUBLISHER_ROLES},
+    error::AppError,
+    models::{
+        AppState, AttachEpisodeRequest, Episode, EpisodeStatus, ReorderEpisodesRequest, Show,
+        UpdateEpisodeRequest, Video,
+    },
+    supabase::{self, SupabaseService},
+};
+
+const EPISODE_ORDER: &str = "season.asc,episode_number.asc";
+
+/// `GET /shows/{id}/episodes` in season and episode order. Drafts are only
+/// listed for publishers.
+pub async fn list_episodes(
+    State(state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path(show_id): Path<Uuid>,
+) -> Result<Json<Vec<Episode>>, AppError> {
+    let supabase = supabase::SupabaseService::for_request(state, user.as_ref());
+    let _: Show = supabase
+        .query_single("shows", "id", &show_id.to_string())
+        .await?;
+
+    let mut params = vec![
+        ("show_id", format!("eq.{}", show_id)),
+        ("order", EPISODE_ORDER.to_string()),
+    ];
+    if !user.is_some_and(|user| user.has_role(PUBLISHER_ROLES)) {
+        params.push((
+            "status",
+            format!("eq.{}", EpisodeStatus::Published.as_str()),
+        ));
+    }
+
+    let (episodes, _) = supabase.query_all("episodes", &params, None).await?;
+    Ok(Json(episodes))
+}
+
+/// `POST /shows/{id}/episodes` attaches an uploaded video to a show. The video
+/// must belong to the caller, unless they are an admin.
+pub async fn attach_episode(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(show_id): Path<Uuid>,
+    Json(request): Json<AttachEpisodeRequest>,
+) -> Result<(StatusCode, Json<Episode>), AppError> {
+    user.require_role(PUBLISHER_ROLES)?;
+    check_position(request.season, request.episode_number)?;
+
+    let supabase = supabase::SupabaseService::for_user(state, &user);
+    let _: Show = supabase
+        .query_single("shows", "id", &show_id.to_string())
+        .await?;
+
+    let video: Video = supabase
+        .query_single("videos", "id", &request.video_id.to_string())
+        .await?;
+    if !user.can_manage(video.user_id.as_deref()) {
+        return Err(AppError::Forbidden(format!(
+            "Video {} belongs to another user",
+            video.id
+        )));
+    }
+
+    if let Some(existing) = find_episode(&supabase, &[("video_id", video.id.clone())]).await? {
+        return Err(AppError::Conflict(format!(
+            "Video {} is already episode {} of show {}",
+            video.id, existing.id, existing.show_id
+        )));
+    }
+    let slot = [
+        ("show_id", show_id.to_string()),
+        ("season", request.season.to_string()),
+        ("episode_number", request.episode_number.to_string()),
+    ];
+    if find_episode(&supabase, &slot).await?.is_some() {
+        return Err(AppError::Conflict(format!(
+            "Season {} episode {} is already taken",
+            request.season, request.episode_number
+        )));
+    }
+
+    let title = request.title.unwrap_or(video.title);
+    if title.trim().is_empty() {
+        return Err(AppError::Validation("Title is required".to_string()));
+    }
+
+    let published_at = (request.status == EpisodeStatus::Published).then(|| Utc::now().naive_utc());
+    let episode: Episode = supabase
+        .insert(
+            "episodes",
+            json!({
+                "show_id": show_id,
+                "video_id": video.id,
+                "season": request.season,
+                "episode_number": request.episode_number,
+                "title": title,
+                "synopsis": request.synopsis,
+                "duration": video.duration,
+                "status": request.status.as_str(),
+                "published_at": published_at,
+            }),
+        )
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(episode)))
+}
+
+/// `PATCH /shows/{id}/episodes/{episode_id}` changes the title, synopsis or
+/// publish state. Publishing stamps `published_at`; unpublishing clears it.
+pub async fn update_episode(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path((show_id, episode_id)): Path<(Uuid, Uuid)>,
+    Json(update): Json<UpdateEpisodeRequest>,
+) -> Result<Json<Episode>, AppError> {
+    user.require_role(PUBLISHER_ROLES)?;
+    if update.title.as_ref().is_some_and(|t| t.trim().is_empty()) {
+        return Err(AppError::Validation("Title cannot be empty".to_string()));
+    }
+
+    let supabase = supabase::SupabaseService::for_user(state, &user);
+    let episode = get_episode(&supabase, show_id, episode_id).await?;
+
+    let mut changes = json!(update);
+    let fields = changes
+        .as_object_mut()
+        .ok_or_else(|| AppError::Internal("Episode update is not an object".to_string()))?;
+    if fields.is_empty() {
+        return Err(AppError::Validation(
+            "No episode fields to update".to_string(),
+        ));
+    }
+
+    match update.status {
+        Some(EpisodeStatus::Published) if episode.status != EpisodeStatus::Published => {
+            fields.insert("published_at".to_string(), json!(Utc::now().naive_utc()));
+        }
+        Some(EpisodeStatus::Draft) => {
+            fields.insert("published_at".to_string(), Value::Null);
+        }
+        _ => {}
+    }
+    fields.insert("updated_at".to_string(), json!(Utc::now().naive_utc()));
+
+    let mut episodes: Vec<Episode> = supabase
+        .update_returning("episodes", &[("id", format!("eq.{}", episode_id))], changes)
+        .await?;
+    if episodes.is_empty() {
+        return Err(episode_not_found(show_id, episode_id));
+    }
+    Ok(Json(episodes.remove(0)))
+}
+
+/// `DELETE /shows/{id}/episodes/{episode_id}` detaches the video from the show,
+/// leaving the video itself in place
+pub async fn detach_episode(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path((show_id, episode_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, AppError> {
+    user.require_role(PUBLISHER_ROLES)?;
+
+    let supabase = supabase::SupabaseService::for_user(state, &user);
+    get_episode(&supabase, show_id, episode_id).await?;
+    supabase
+        .delete("episodes", "id", &episode_id.to_string())
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `PUT /shows/{id}/episodes/order` moves episodes to new seasons and numbers in
+/// one go, so numbers can be swapped. Episodes left out keep their place.
+pub async fn reorder_episodes(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(show_id): Path<Uuid>,
+    Json(request): Json<ReorderEpisodesRequest>,
+) -> Result<Json<Vec<Episode>>, AppError> {
+    user.require_role(PUBLISHER_ROLES)?;
+    if request.episodes.is_empty() {
+        return Err(AppError::Validation(
+            "At least one episode position is required".to_string(),
+        ));
+    }
+
+    let supabase = supabase::SupabaseService::for_user(state, &user);
+    let (current, _): (Vec<Episode>, i64) = supabase
+        .query_all("episodes", &[("show_id", format!("eq.{}", show_id))], None)
+        .await?;
+
+    let mut positions: HashMap<String, (i32, i32)> = current
+        .into_iter()
+        .map(|episode| (episode.id, (episode.season, episode.episode_number)))
+        .collect();
+    let mut moved = HashSet::new();
+    for position in &request.episodes {
+        check_position(position.season, position.episode_number)?;
+        let id = position.id.to_string();
+        if !moved.insert(id.clone()) {
+            return Err(AppError::Validation(format!(
+                "Episode {} is listed more than once",
+                id
+            )));
+        }
+        match positions.get_mut(&id) {
+            Some(slot) => *slot = (position.season, position.episode_number),
+            None => return Err(episode_not_found(show_id, position.id)),
+        }
+    }
+
+    let mut taken = HashSet::new();
+    for (season, episode_number) in positions.values() {
+        if !taken.insert((season, episode_number)) {
+            return Err(AppError::Conflict(format!(
+                "Season {} episode {} would be used by more than one episode",
+                season, episode_number
+            )));
+        }
+    }
+
+    let (episodes, _) = supabase
+        .query_rpc(
+            "reorder_episodes",
+            json!({ "show_param": show_id, "positions": request.episodes }),
+            &[],
+            None,
+        )
+        .await?;
+    Ok(Json(episodes))
+}
+
+async fn get_episode(
+    supabase: &SupabaseService,
+    show_id: Uuid,
+    episode_id: Uuid,
+) -> Result<Episode, AppError> {
+    let filters = [
+        ("id", episode_id.to_string()),
+        ("show_id", show_id.to_string()),
+    ];
+    find_episode(supabase, &filters)
+        .await?
+        .ok_or_else(|| episode_not_found(show_id, episode_id))
+}
+
+/// The episode matching every `column = value` filter, if there is one
+async fn find_episode(
+    supabase: &SupabaseService,
+    filters: &[(&str, String)],
+) -> Result<Option<Episode>, AppError> {
+    let params: Vec<(&str, String)> = filters
+        .iter()
+        .map(|(column, value)| (*column, format!("eq.{}", value)))
+        .collect();
+    let (mut episodes, _): (Vec<Episode>, i64) = supabase
+        .query_all("episodes", &params, Some((0, 0)))
+        .await?;
+
+    Ok((!episodes.is_empty()).then(|| episodes.remove(0)))
+}
+
+fn check_position(season: i32, episode_number: i32) -> Result<(), AppError> {
+    if season < 1 || episode_number < 1 {
+        return Err(AppError::Validation(
+            "Season and episode numbers start at 1".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn episode_not_found(show_id: Uuid, episode_id: Uuid) -> AppError {
+    AppError::NotFound(format!(
+        "Episode {} not found in show {}",
+        episode_id, show_id
+    ))
+}
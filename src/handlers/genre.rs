@@ -0,0 +1,242 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use chrono::Utc;
+use serde_json::json;
+
+use crate::{
+    auth::{AuthUser, PUBLISHER_ROLES, ROLE_ADMIN},
+    error::AppError,
+    models::{AppState, Genre, ListGenresQuery, UpdateGenreRequest},
+    supabase::{self, SupabaseService},
+};
+
+const GENRE_ORDER: &str = "sort_order.asc,slug.asc";
+
+/// `GET /genres` in display order. Publishers can ask for inactive genres too.
+pub async fn list_genres(
+    State(state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Query(query): Query<ListGenresQuery>,
+) -> Result<Json<Vec<Genre>>, AppError> {
+    let include_inactive = query.include_inactive
+        && user
+            .as_ref()
+            .is_some_and(|user| user.has_role(PUBLISHER_ROLES));
+
+    let mut params = vec![("order", GENRE_ORDER.to_string())];
+    if !include_inactive {
+        params.push(("active", "eq.true".to_string()));
+    }
+
+    let supabase = supabase::SupabaseService::for_request(state, user.as_ref());
+    let (genres, _) = supabase.query_all("genres", &params, None).await?;
+    Ok(Json(genres))
+}
+
+pub async fn get_genre(
+    State(state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path(slug): Path<String>,
+) -> Result<Json<Genre>, AppError> {
+    check_slug(&slug)?;
+
+    let supabase = supabase::SupabaseService::for_request(state, user.as_ref());
+    let genre: Genre = supabase.query_single("genres", "slug", &slug).await?;
+    Ok(Json(genre))
+}
+
+pub async fn create_genre(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(genre): Json<Genre>,
+) -> Result<(StatusCode, Json<Genre>), AppError> {
+    user.require_role(&[ROLE_ADMIN])?;
+    check_slug(&genre.slug)?;
+    check_names(Some(&genre.display_name), Some(&genre.localized_names))?;
+
+    let supabase = supabase::SupabaseService::for_user(state, &user);
+    if find_genre(&supabase, &genre.slug).await?.is_some() {
+        return Err(AppError::Conflict(format!(
+            "Genre {} already exists",
+            genre.slug
+        )));
+    }
+
+    let created: Genre = supabase
+        .insert(
+            "genres",
+            json!({
+                "slug": genre.slug,
+                "display_name": genre.display_name.trim(),
+                "localized_names": genre.localized_names,
+                "sort_order": genre.sort_order,
+                "active": genre.active,
+            }),
+        )
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+/// `PATCH /genres/{slug}` changes only the fields sent and refreshes `updated_at`
+pub async fn update_genre(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(slug): Path<String>,
+    Json(update): Json<UpdateGenreRequest>,
+) -> Result<Json<Genre>, AppError> {
+    user.require_role(&[ROLE_ADMIN])?;
+    check_slug(&slug)?;
+    check_names(
+        update.display_name.as_ref(),
+        update.localized_names.as_ref(),
+    )?;
+
+    let mut changes = json!(update);
+    let fields = changes
+        .as_object_mut()
+        .ok_or_else(|| AppError::Internal("Genre update is not an object".to_string()))?;
+    if fields.is_empty() {
+        return Err(AppError::Validation(
+            "No genre fields to update".to_string(),
+        ));
+    }
+    fields.insert("updated_at".to_string(), json!(Utc::now().naive_utc()));
+
+    let supabase = supabase::SupabaseService::for_user(state, &user);
+    let mut genres: Vec<Genre> = supabase
+        .update_returning("genres", &[("slug", format!("eq.{}", slug))], changes)
+        .await?;
+
+    if genres.is_empty() {
+        return Err(genre_not_found(&slug));
+    }
+    Ok(Json(genres.remove(0)))
+}
+
+/// `DELETE /genres/{slug}`, refused while any show still carries the genre.
+/// Deactivating it hides it from new shows instead.
+pub async fn delete_genre(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(slug): Path<String>,
+) -> Result<StatusCode, AppError> {
+    user.require_role(&[ROLE_ADMIN])?;
+    check_slug(&slug)?;
+
+    // A trigger in queries/genres.sql refuses the delete while a show uses the
+    // genre, so a show saved in the meantime can't be left pointing at nothing
+    let supabase = supabase::SupabaseService::for_user(state, &user);
+    match supabase.delete("genres", "slug", &slug).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(AppError::NotFound(_)) => Err(genre_not_found(&slug)),
+        Err(AppError::Conflict(_)) => Err(AppError::Conflict(format!(
+            "Genre {} is used by shows; deactivate it instead",
+            slug
+        ))),
+        Err(e) => Err(e),
+    }
+}
+
+/// Check that every slug names an active genre, returning them without duplicates
+pub async fn check_genres(
+    supabase: &SupabaseService,
+    slugs: &[String],
+) -> Result<Vec<String>, AppError> {
+    let mut unique: Vec<String> = Vec::new();
+    for slug in slugs {
+        check_slug(slug)?;
+        if !unique.contains(slug) {
+            unique.push(slug.clone());
+        }
+    }
+    if unique.is_empty() {
+        return Err(AppError::Validation(
+            "At least one genre is required".to_string(),
+        ));
+    }
+
+    let (known, _): (Vec<Genre>, i64) = supabase
+        .query_all(
+            "genres",
+            &[
+                ("slug", format!("in.({})", unique.join(","))),
+                ("active", "eq.true".to_string()),
+            ],
+            None,
+        )
+        .await?;
+
+    let unknown: Vec<&str> = unique
+        .iter()
+        .filter(|slug| !known.iter().any(|genre| &genre.slug == *slug))
+        .map(String::as_str)
+        .collect();
+    if !unknown.is_empty() {
+        return Err(AppError::Validation(format!(
+            "Unknown or inactive genres: {}",
+            unknown.join(", ")
+        )));
+    }
+
+    Ok(unique)
+}
+
+/// Slugs are lowercase letters and digits, separated by single hyphens
+pub fn check_slug(slug: &str) -> Result<(), AppError> {
+    let valid = !slug.is_empty()
+        && slug.split('-').all(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        });
+    if valid {
+        return Ok(());
+    }
+
+    Err(AppError::Validation(format!(
+        "Invalid genre slug {:?}; use lowercase letters, digits and single hyphens",
+        slug
+    )))
+}
+
+fn check_names(
+    display_name: Option<&String>,
+    localized_names: Option<&HashMap<String, String>>,
+) -> Result<(), AppError> {
+    if display_name.is_some_and(|name| name.trim().is_empty()) {
+        return Err(AppError::Validation(
+            "Display name cannot be empty".to_string(),
+        ));
+    }
+    if let Some(names) = localized_names
+        && let Some((locale, _)) = names
+            .iter()
+            .find(|(locale, name)| locale.trim().is_empty() || name.trim().is_empty())
+    {
+        return Err(AppError::Validation(format!(
+            "Localized name for {:?} cannot be empty",
+            locale
+        )));
+    }
+    Ok(())
+}
+
+async fn find_genre(supabase: &SupabaseService, slug: &str) -> Result<Option<Genre>, AppError> {
+    match supabase.query_single("genres", "slug", slug).await {
+        Ok(genre) => Ok(Some(genre)),
+        Err(AppError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn genre_not_found(slug: &str) -> AppError {
+    AppError::NotFound(format!("Genre {} not found", slug))
+}
@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use uuid::Uuid;
+
+use crate::{
+    auth::AuthUser,
+    error::AppError,
+    jobs::Job,
+    models::{AppState, Video},
+    supabase::SupabaseService,
+};
+
+/// `GET /jobs/{id}` reports progress to the uploader of the job's video, or an admin
+pub async fn get_job(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Job>, AppError> {
+    let job = state
+        .jobs
+        .get(&id.to_string())
+        .ok_or(AppError::NotFound(format!("Job {} not found", id)))?;
+
+    // RLS decides whether the caller may see the video at all
+    let supabase = SupabaseService::for_user(state.clone(), &user);
+    let video: Video = supabase.query_single("videos", "id", &job.video_id).await?;
+    if !user.can_manage(video.user_id.as_deref()) {
+        return Err(AppError::Forbidden(format!(
+            "Job {} belongs to another user",
+            id
+        )));
+    }
+
+    Ok(Json(job))
+}
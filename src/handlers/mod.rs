@@ -1,2 +1,7 @@
+pub mod episode;
+pub mod genre;
+pub mod job;
 pub mod show;
+pub mod storage;
+pub mod tus;
 pub mod video;
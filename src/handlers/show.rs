@@ -1,97 +1,301 @@
 use std::sync::Arc;
 
 use anyhow::Result;
-use axum::{Json, extract::State};
-use reqwest::StatusCode;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use chrono::{NaiveDate, Utc};
 use serde_json::json;
+use uuid::Uuid;
 
 use crate::{
-    models::{AppState, CreateShowResponse, Show},
+    auth::{AuthUser, PUBLISHER_ROLES},
+    config::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE},
+    error::{AppError, FieldErrors},
+    handlers::genre::{check_genres, check_slug},
+    models::{
+        AppState, CreateShowRequest, CreateShowResponse, ListShowsQuery, Show, ShowListResponse,
+        ShowStatus, UpdateShowRequest,
+    },
     supabase,
 };
 
-pub async fn create_show(
+// Ratings are on a five-point scale
+const MIN_RATING: f32 = 0.0;
+const MAX_RATING: f32 = 5.0;
+// Longest tag a show may carry, in characters
+const MAX_TAG_LEN: usize = 50;
+
+/// `GET /shows`, best rated first. A genre filter goes through the
+/// `get_shows_by_genre` database function; the other filters apply to either.
+pub async fn list_shows(
+    State(state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Query(query): Query<ListShowsQuery>,
+) -> Result<Json<ShowListResponse>, AppError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0);
+
+    // Tie-break on id so pages stay stable when ratings repeat
+    let mut params = vec![("order", "rating.desc,release_date.desc,id.asc".to_string())];
+    if let Some(status) = &query.status {
+        params.push(("status", format!("eq.{}", status.as_str())));
+    }
+    if let Some(tag) = &query.tag {
+        params.push(("tags", format!("cs.{{\"{}\"}}", normalize_tag(tag)?)));
+    }
+    if let Some(min_rating) = query.min_rating {
+        params.push(("rating", format!("gte.{}", min_rating)));
+    }
+    if let Some(from) = &query.released_from {
+        params.push((
+            "release_date",
+            format!("gte.{}", parse_date("released_from", from)?),
+        ));
+    }
+    if let Some(to) = &query.released_to {
+        params.push((
+            "release_date",
+            format!("lte.{}", parse_date("released_to", to)?),
+        ));
+    }
+
+    let range = Some(supabase::page_range(offset, limit));
+    let supabase = supabase::SupabaseService::for_request(state, user.as_ref());
+    let (shows, total): (Vec<Show>, i64) = match &query.genre {
+        Some(genre) => {
+            check_slug(genre)?;
+            supabase
+                .query_rpc(
+                    "get_shows_by_genre",
+                    json!({ "genre_param": genre }),
+                    &params,
+                    range,
+                )
+                .await?
+        }
+        None => supabase.query_all("shows", &params, range).await?,
+    };
+
+    let next_offset = supabase::next_offset(offset, shows.len(), total);
+
+    Ok(Json(ShowListResponse {
+        shows,
+        total,
+        limit,
+        offset,
+        next_offset,
+    }))
+}
+
+pub async fn get_show(
+    State(state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Show>, AppError> {
+    let supabase = supabase::SupabaseService::for_request(state, user.as_ref());
+    let show: Show = supabase
+        .query_single("shows", "id", &id.to_string())
+        .await?;
+
+    Ok(Json(show))
+}
+
+/// `PATCH /shows/{id}` changes only the fields sent and refreshes `updated_at`
+pub async fn update_show(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(mut update): Json<UpdateShowRequest>,
+) -> Result<Json<Show>, AppError> {
+    user.require_role(PUBLISHER_ROLES)?;
+
+    let supabase = supabase::SupabaseService::for_user(state, &user);
+    let mut errors = FieldErrors::default();
+    if let Some(title) = update.title.take() {
+        update.title = errors.check("title", check_title(&title))?;
+    }
+    if let Some(release_date) = &update.release_date {
+        errors.check("release_date", parse_date("release_date", release_date))?;
+    }
+    if let Some(thumbnail_url) = &update.thumbnail_url {
+        errors.check("thumbnail_url", check_thumbnail_url(thumbnail_url))?;
+    }
+    if let Some(rating) = update.rating {
+        errors.check("rating", check_rating(rating))?;
+    }
+    if let Some(status) = &update.status {
+        errors.check("status", check_status(status))?;
+    }
+    if let Some(genres) = update.genres.take() {
+        update.genres = errors.check("genres", check_genres(&supabase, &genres).await)?;
+    }
+    if let Some(tags) = update.tags.take() {
+        update.tags = errors.check("tags", normalize_tags(tags))?;
+    }
+    errors.finish()?;
+
+    let mut changes = json!(update);
+    let fields = changes
+        .as_object_mut()
+        .ok_or_else(|| AppError::Internal("Show update is not an object".to_string()))?;
+    if fields.is_empty() {
+        return Err(AppError::Validation("No show fields to update".to_string()));
+    }
+    fields.insert("updated_at".to_string(), json!(Utc::now().naive_utc()));
+
+    let mut shows: Vec<Show> = supabase
+        .update_returning("shows", &[("id", format!("eq.{}", id))], changes)
+        .await?;
+
+    // RLS hides rows the user may not change, so those look missing too
+    if shows.is_empty() {
+        return Err(AppError::NotFound(format!("Show {} not found", id)));
+    }
+    Ok(Json(shows.remove(0)))
+}
+
+pub async fn delete_show(
     State(state): State<Arc<AppState>>,
-    Json(show): Json<Show>,
-) -> Result<Json<CreateShowResponse>, (StatusCode, String)> {
-    // Validate genre
-    let valid_genres = vec!["Revenge", "Billionare", "Asian", "Romance"];
-    if !valid_genres.contains(&show.genre.as_str()) {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            format!("Invalid genre. Must be one of: {}", valid_genres.join(", ")),
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    user.require_role(PUBLISHER_ROLES)?;
+
+    let supabase = supabase::SupabaseService::for_user(state, &user);
+    let _: Show = supabase
+        .query_single("shows", "id", &id.to_string())
+        .await?;
+    supabase.delete("shows", "id", &id.to_string()).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn check_title(title: &str) -> Result<String, AppError> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err(AppError::Validation("title cannot be empty".to_string()));
+    }
+    Ok(title.to_string())
+}
+
+fn check_thumbnail_url(url: &str) -> Result<(), AppError> {
+    if url.trim().is_empty() {
+        return Err(AppError::Validation(
+            "thumbnail_url cannot be empty".to_string(),
         ));
     }
+    Ok(())
+}
+
+fn check_rating(rating: f32) -> Result<f32, AppError> {
+    if (MIN_RATING..=MAX_RATING).contains(&rating) {
+        return Ok(rating);
+    }
+    Err(AppError::Validation(format!(
+        "rating must be between {} and {}",
+        MIN_RATING, MAX_RATING
+    )))
+}
+
+fn check_status(status: &str) -> Result<ShowStatus, AppError> {
+    ShowStatus::parse(status).ok_or_else(|| {
+        let statuses: Vec<&str> = ShowStatus::ALL.iter().map(ShowStatus::as_str).collect();
+        AppError::Validation(format!("status must be one of: {}", statuses.join(", ")))
+    })
+}
+
+/// Trim, lowercase and deduplicate tags
+fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, AppError> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = normalize_tag(&tag)?;
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    Ok(normalized)
+}
+
+/// Tags are letters, digits, spaces and hyphens, so they can be matched in a
+/// PostgREST array filter without escaping
+fn normalize_tag(tag: &str) -> Result<String, AppError> {
+    let tag = tag.trim().to_lowercase();
+    let valid = !tag.is_empty()
+        && tag.chars().count() <= MAX_TAG_LEN
+        && tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == ' ' || c == '-');
+    if valid {
+        return Ok(tag);
+    }
+
+    Err(AppError::Validation(format!(
+        "Invalid tag {:?}; tags are up to {} letters, digits, spaces or hyphens",
+        tag, MAX_TAG_LEN
+    )))
+}
+
+fn parse_date(field: &str, value: &str) -> Result<NaiveDate, AppError> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| AppError::Validation(format!("{} must be a date as YYYY-MM-DD", field)))
+}
+
+pub async fn create_show(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(show): Json<CreateShowRequest>,
+) -> Result<Json<CreateShowResponse>, AppError> {
+    user.require_role(PUBLISHER_ROLES)?;
 
     // Create Supabase service
-    let supabase = supabase::SupabaseService::new(state).await?;
+    let supabase = supabase::SupabaseService::for_user(state, &user);
+
+    // Validate every field, genres against the genres table, and report all problems at once
+    let mut errors = FieldErrors::default();
+    let title = errors.check("title", check_title(&show.title))?;
+    let release_date = errors.check(
+        "release_date",
+        parse_date("release_date", &show.release_date),
+    )?;
+    errors.check("thumbnail_url", check_thumbnail_url(&show.thumbnail_url))?;
+    let rating = errors.check(
+        "rating",
+        show.rating
+            .ok_or_else(|| AppError::Validation("rating is required".to_string()))
+            .and_then(check_rating),
+    )?;
+    let status = errors.check("status", check_status(&show.status))?;
+    let genres = errors.check("genres", check_genres(&supabase, &show.genres).await)?;
+    let tags = errors.check("tags", normalize_tags(show.tags))?;
+    errors.finish()?;
 
     // Prepare show data
     let show_data = json!({
-        "title": show.title,
+        "title": title,
         "description": show.description,
-        "release_date": show.release_date,
+        "release_date": release_date,
         "thumbnail_url": show.thumbnail_url,
-        "episode_count": show.episode_count,
-        "genre": show.genre,
-        "rating": show.rating,
-        "status": show.status,
+        "genres": genres,
+        "tags": tags,
+        "rating": rating,
+        "status": status,
     });
 
-    // Insert into database
-    let url = format!("{}/rest/v1/shows", supabase.state.supabase_url);
-
-    let client = reqwest::Client::new();
-    let response = client
-        .post(&url)
-        .header("apikey", &supabase.state.supabase_api_key)
-        .header(
-            "Authorization",
-            format!("Bearer {}", &supabase.state.supabase_api_key),
-        )
-        .header("Content-Type", "application/json")
-        .header("Prefer", "return=representation")
-        .json(&show_data)
-        .send()
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    if !response.status().is_success() {
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!(
-                "Failed to insert show: {} - {}",
-                response.status(),
-                response.text().await.unwrap_or_default()
-            ),
-        ));
-    }
-
-    // Parse response to get the created show's ID
-    let created_show: Vec<serde_json::Value> = response.json().await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to parse response: {}", e),
-        )
-    })?;
-
-    if created_show.is_empty() {
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "No show data returned after creation".to_string(),
-        ));
-    }
+    // Constraint and genre trigger rejections come back as client errors
+    let created: Show = supabase.insert("shows", show_data).await?;
 
-    let show_id = created_show[0]["id"]
-        .as_str()
-        .ok_or((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to get show ID from response".to_string(),
-        ))?
-        .to_string();
+    let id = created
+        .id
+        .ok_or_else(|| AppError::UpstreamDatabase("Created show has no id".to_string()))?;
 
     Ok(Json(CreateShowResponse {
-        id: show_id,
-        title: show.title,
+        id,
+        title: created.title,
     }))
 }
@@ -0,0 +1,303 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{HeaderMap, HeaderValue, header},
+    response::{IntoResponse, Response},
+};
+use reqwest::StatusCode;
+
+use crate::{
+    error::AppError,
+    models::AppState,
+    storage::{ByteRange, SOURCES_PREFIX, StorageBackend},
+};
+
+/// Serve objects for storage backends that don't have their own public URLs. Only
+/// routed without playback signing, which `/videos/{id}/stream` and `/media` check.
+pub async fn serve_object(
+    State(state): State<Arc<AppState>>,
+    Path(path): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    // Raw uploads are only ever read by the transcoder
+    if !state.storage.serves_through_app() || path.starts_with(SOURCES_PREFIX) {
+        return Err(AppError::NotFound(format!("{} not found", path)));
+    }
+
+    object_response(state.storage.as_ref(), &path, &headers).await
+}
+
+/// Proxy a stored object, honouring `Range` and `If-Range` so players can seek.
+/// Answers 206 with the requested bytes, 200 with the whole object when there is
+/// no usable range, and 416 when the range lies outside the object.
+pub async fn object_response(
+    storage: &dyn StorageBackend,
+    path: &str,
+    request_headers: &HeaderMap,
+) -> Result<Response, AppError> {
+    // A malformed or multi-range header is ignored and the whole object sent
+    let range = request_headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(ByteRange::parse);
+    let if_range = request_headers
+        .get(header::IF_RANGE)
+        .and_then(|v| v.to_str().ok());
+
+    // If-Range only allows a partial response while the client's copy is current;
+    // otherwise the range is ignored and the whole object sent again
+    let range = match (range, if_range) {
+        (Some(range), Some(validator)) => storage
+            .etag(path)
+            .await?
+            .is_some_and(|etag| strong_match(&etag, validator))
+            .then_some(range),
+        (range, _) => range,
+    };
+    let partial = range.is_some();
+
+    let object = match storage.get_range(path, range).await {
+        Ok(object) => object,
+        Err(AppError::Rejected(StatusCode::RANGE_NOT_SATISFIABLE, message)) => {
+            return Ok(range_not_satisfiable(storage, path, message).await);
+        }
+        Err(err) => return Err(err),
+    };
+
+    let status = if partial {
+        StatusCode::PARTIAL_CONTENT
+    } else {
+        StatusCode::OK
+    };
+    let content_length = object.content_length();
+
+    let mut response = (status, Body::from_stream(object.stream)).into_response();
+    let headers = response.headers_mut();
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Ok(value) = HeaderValue::from_str(&object.content_type) {
+        headers.insert(header::CONTENT_TYPE, value);
+    }
+    headers.insert(header::CONTENT_LENGTH, content_length.into());
+    if partial {
+        let content_range = format!(
+            "bytes {}-{}/{}",
+            object.start, object.end, object.total_size
+        );
+        if let Ok(value) = HeaderValue::from_str(&content_range) {
+            headers.insert(header::CONTENT_RANGE, value);
+        }
+    }
+    if let Some(value) = object
+        .etag
+        .and_then(|etag| HeaderValue::from_str(&etag).ok())
+    {
+        headers.insert(header::ETAG, value);
+    }
+
+    Ok(response)
+}
+
+/// A 416 response that tells the client how large the object actually is
+async fn range_not_satisfiable(
+    storage: &dyn StorageBackend,
+    path: &str,
+    message: String,
+) -> Response {
+    let mut response =
+        AppError::Rejected(StatusCode::RANGE_NOT_SATISFIABLE, message).into_response();
+
+    // The last byte is enough to learn the size without reading the object, and
+    // only an empty object has no last byte
+    let total_size = match storage.get_range(path, Some(ByteRange::Suffix(1))).await {
+        Ok(object) => Some(object.total_size),
+        Err(AppError::Rejected(StatusCode::RANGE_NOT_SATISFIABLE, _)) => Some(0),
+        Err(_) => None,
+    };
+    if let Some(value) =
+        total_size.and_then(|size| HeaderValue::from_str(&format!("bytes */{}", size)).ok())
+    {
+        response.headers_mut().insert(header::CONTENT_RANGE, value);
+    }
+    response
+        .headers_mut()
+        .insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    response
+}
+
+/// Strong ETag comparison, as If-Range requires. Weak validators and dates never match.
+fn strong_match(etag: &str, validator: &str) -> bool {
+    let validator = validator.trim();
+    !etag.starts_with("W/") && !validator.starts_with("W/") && etag == validator
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::to_bytes;
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    const PATH: &str = "id/video.mp4";
+
+    async fn storage_with(content: &'static [u8]) -> MemoryStorage {
+        let storage = MemoryStorage::new("http://localhost");
+        storage
+            .put(PATH, Bytes::from_static(content), "video/mp4")
+            .await
+            .unwrap();
+        storage
+    }
+
+    async fn get(storage: &MemoryStorage, headers: &[(header::HeaderName, &str)]) -> Response {
+        let mut request_headers = HeaderMap::new();
+        for (name, value) in headers {
+            request_headers.insert(name, HeaderValue::from_str(value).unwrap());
+        }
+        object_response(storage, PATH, &request_headers)
+            .await
+            .unwrap()
+    }
+
+    fn header_value(response: &Response, name: header::HeaderName) -> Option<&str> {
+        response.headers().get(name).and_then(|v| v.to_str().ok())
+    }
+
+    async fn body(response: Response) -> Bytes {
+        to_bytes(response.into_body(), usize::MAX).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn inclusive_range() {
+        let storage = storage_with(b"0123456789").await;
+        let response = get(&storage, &[(header::RANGE, "bytes=2-5")]).await;
+
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            header_value(&response, header::CONTENT_RANGE),
+            Some("bytes 2-5/10")
+        );
+        assert_eq!(header_value(&response, header::CONTENT_LENGTH), Some("4"));
+        assert_eq!(body(response).await, "2345");
+    }
+
+    #[tokio::test]
+    async fn open_ended_range() {
+        let storage = storage_with(b"0123456789").await;
+        let response = get(&storage, &[(header::RANGE, "bytes=7-")]).await;
+
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            header_value(&response, header::CONTENT_RANGE),
+            Some("bytes 7-9/10")
+        );
+        assert_eq!(body(response).await, "789");
+    }
+
+    #[tokio::test]
+    async fn suffix_range() {
+        let storage = storage_with(b"0123456789").await;
+        let response = get(&storage, &[(header::RANGE, "bytes=-3")]).await;
+
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            header_value(&response, header::CONTENT_RANGE),
+            Some("bytes 7-9/10")
+        );
+        assert_eq!(body(response).await, "789");
+    }
+
+    #[tokio::test]
+    async fn unsatisfiable_range() {
+        let storage = storage_with(b"0123456789").await;
+        let response = get(&storage, &[(header::RANGE, "bytes=10-")]).await;
+
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(
+            header_value(&response, header::CONTENT_RANGE),
+            Some("bytes */10")
+        );
+    }
+
+    #[tokio::test]
+    async fn multiple_ranges_send_whole_object() {
+        let storage = storage_with(b"0123456789").await;
+        let response = get(&storage, &[(header::RANGE, "bytes=0-1,4-5")]).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header_value(&response, header::CONTENT_RANGE), None);
+        assert_eq!(body(response).await, "0123456789");
+    }
+
+    #[tokio::test]
+    async fn zero_length_object() {
+        let storage = storage_with(b"").await;
+
+        let response = get(&storage, &[]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header_value(&response, header::CONTENT_LENGTH), Some("0"));
+        assert!(body(response).await.is_empty());
+
+        let response = get(&storage, &[(header::RANGE, "bytes=0-")]).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(
+            header_value(&response, header::CONTENT_RANGE),
+            Some("bytes */0")
+        );
+    }
+
+    #[tokio::test]
+    async fn current_if_range_keeps_range() {
+        let storage = storage_with(b"0123456789").await;
+        let etag = storage.etag(PATH).await.unwrap().unwrap();
+        let response = get(
+            &storage,
+            &[(header::RANGE, "bytes=0-1"), (header::IF_RANGE, &etag)],
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(header_value(&response, header::ETAG), Some(etag.as_str()));
+        assert_eq!(body(response).await, "01");
+    }
+
+    #[tokio::test]
+    async fn stale_if_range_sends_whole_object() {
+        let storage = storage_with(b"0123456789").await;
+        let stale = storage.etag(PATH).await.unwrap().unwrap();
+        storage
+            .put(PATH, Bytes::from_static(b"abcdefghij"), "video/mp4")
+            .await
+            .unwrap();
+        let response = get(
+            &storage,
+            &[(header::RANGE, "bytes=0-1"), (header::IF_RANGE, &stale)],
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header_value(&response, header::CONTENT_RANGE), None);
+        assert_eq!(body(response).await, "abcdefghij");
+    }
+
+    #[tokio::test]
+    async fn weak_or_date_if_range_sends_whole_object() {
+        let storage = storage_with(b"0123456789").await;
+        let etag = storage.etag(PATH).await.unwrap().unwrap();
+
+        for validator in [
+            format!("W/{}", etag),
+            "Wed, 21 Oct 2015 07:28:00 GMT".into(),
+        ] {
+            let response = get(
+                &storage,
+                &[(header::RANGE, "bytes=0-1"), (header::IF_RANGE, &validator)],
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK, "{}", validator);
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use std::{path::Path, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::{Path as UrlPath, Request, State},
+    http::{HeaderMap, HeaderValue, Method, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use reqwest::StatusCode;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    auth::{AuthUser, PUBLISHER_ROLES},
+    error::AppError,
+    handlers::video::create_video_row,
+    jobs::JobInput,
+    models::AppState,
+    supabase,
+    tus::{TUS_EXTENSIONS, TUS_VERSION, TusUpload, encode_metadata, parse_metadata},
+    video_processor::VideoProcessor,
+};
+
+const TUS_RESUMABLE: &str = "tus-resumable";
+const UPLOAD_LENGTH: &str = "upload-length";
+const UPLOAD_OFFSET: &str = "upload-offset";
+const UPLOAD_METADATA: &str = "upload-metadata";
+const UPLOAD_EXPIRES: &str = "upload-expires";
+// Let the client find the video row and processing job once the upload completes
+const VIDEO_ID: &str = "x-video-id";
+const JOB_ID: &str = "x-job-id";
+
+/// Add `Tus-Resumable` to every upload response, including errors, and the
+/// discovery headers to `OPTIONS /uploads`. The CORS layer answers every OPTIONS
+/// request itself, so this runs outside it rather than as a handler.
+pub async fn tus_protocol(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    let is_upload = path == "/uploads" || path.starts_with("/uploads/");
+    let is_discovery = request.method() == Method::OPTIONS && path == "/uploads";
+    let mut response = next.run(request).await;
+
+    if is_upload {
+        response.headers_mut().extend(tus_headers());
+    }
+    if is_discovery {
+        let headers = response.headers_mut();
+        headers.insert("tus-version", HeaderValue::from_static(TUS_VERSION));
+        headers.insert("tus-extension", HeaderValue::from_static(TUS_EXTENSIONS));
+        headers.insert("tus-max-size", state.max_upload_bytes.into());
+    }
+
+    response
+}
+
+pub async fn create_upload(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    check_tus_version(&headers)?;
+    user.require_role(PUBLISHER_ROLES)?;
+
+    let length = header_u64(&headers, UPLOAD_LENGTH)?.ok_or(AppError::Validation(
+        "Upload-Length header is required".to_string(),
+    ))?;
+    if length > state.max_upload_bytes {
+        return Err(AppError::Rejected(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "Upload exceeds the maximum size of {} bytes",
+                state.max_upload_bytes
+            ),
+        ));
+    }
+
+    let metadata = match headers.get(UPLOAD_METADATA) {
+        Some(value) => parse_metadata(value.to_str().unwrap_or_default())?,
+        None => Default::default(),
+    };
+
+    // Reject an unknown profile before any bytes are sent
+    state
+        .encoding_profiles
+        .get(metadata.get("profile").map(String::as_str))?;
+
+    let upload = state.tus.create(length, metadata, &user.id).await?;
+
+    let mut response_headers = tus_headers();
+    response_headers.insert(
+        header::LOCATION,
+        HeaderValue::from_str(&format!("/uploads/{}", upload.id))
+            .map_err(|e| AppError::Internal(e.to_string()))?,
+    );
+    insert_expires(&state, &upload, &mut response_headers).await;
+
+    Ok((StatusCode::CREATED, response_headers).into_response())
+}
+
+pub async fn upload_offset(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    UrlPath(id): UrlPath<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    check_tus_version(&headers)?;
+
+    let (upload, offset) = state.tus.get(&id.to_string()).await?;
+    check_owner(&user, &upload)?;
+
+    let mut headers = tus_headers();
+    headers.insert(UPLOAD_OFFSET, offset.into());
+    headers.insert(UPLOAD_LENGTH, upload.length.into());
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    if !upload.metadata.is_empty()
+        && let Ok(value) = HeaderValue::from_str(&encode_metadata(&upload.metadata))
+    {
+        headers.insert(UPLOAD_METADATA, value);
+    }
+
+    Ok((StatusCode::OK, headers).into_response())
+}
+
+pub async fn append_upload(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    UrlPath(id): UrlPath<Uuid>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, AppError> {
+    check_tus_version(&headers)?;
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if content_type != "application/offset+octet-stream" {
+        return Err(AppError::Rejected(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Content-Type must be application/offset+octet-stream".to_string(),
+        ));
+    }
+
+    let id = id.to_string();
+    let _lock = state.tus.lock(&id)?;
+    let (mut upload, current_offset) = state.tus.get(&id).await?;
+    check_owner(&user, &upload)?;
+
+    let offset = header_u64(&headers, UPLOAD_OFFSET)?.ok_or(AppError::Validation(
+        "Upload-Offset header is required".to_string(),
+    ))?;
+    if offset != current_offset {
+        return Err(AppError::Conflict(format!(
+            "Upload-Offset {} does not match current offset {}",
+            offset, current_offset
+        )));
+    }
+
+    // A PATCH at the final offset retries finishing an upload whose completion
+    // step failed, and is a no-op once the video has been created
+    let new_offset = if current_offset < upload.length {
+        state.tus.append(&upload, offset, body).await?
+    } else {
+        current_offset
+    };
+
+    let mut response_headers = tus_headers();
+    response_headers.insert(UPLOAD_OFFSET, new_offset.into());
+
+    if new_offset < upload.length {
+        insert_expires(&state, &upload, &mut response_headers).await;
+    } else {
+        let (video_id, job_id) = match upload.video_id.clone() {
+            Some(video_id) => (video_id, None),
+            None => {
+                if let Err(e) = VideoProcessor::inspect(
+                    &state.tus.data_path(&upload.id),
+                    state.max_video_duration_secs,
+                )
+                .await
+                {
+                    // A rejected file will never become a video, so don't keep it around
+                    if e.status() == StatusCode::UNPROCESSABLE_ENTITY {
+                        if let Err(remove_error) = state.tus.remove(&upload.id).await {
+                            error!(
+                                "Failed to remove rejected tus upload {}: {}",
+                                upload.id, remove_error
+                            );
+                        } else {
+                            info!("Removed tus upload {}: {}", upload.id, e);
+                        }
+                    }
+                    return Err(e);
+                }
+                finish_upload(state.clone(), &user, &mut upload).await?
+            }
+        };
+        if let Ok(value) = HeaderValue::from_str(&video_id) {
+            response_headers.insert(VIDEO_ID, value);
+        }
+        if let Some(value) = job_id.and_then(|id| HeaderValue::from_str(&id).ok()) {
+            response_headers.insert(JOB_ID, value);
+        }
+    }
+
+    Ok((StatusCode::NO_CONTENT, response_headers).into_response())
+}
+
+pub async fn terminate_upload(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    UrlPath(id): UrlPath<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    check_tus_version(&headers)?;
+
+    let id = id.to_string();
+    let _lock = state.tus.lock(&id)?;
+    let (upload, _) = state.tus.get(&id).await?;
+    check_owner(&user, &upload)?;
+    state.tus.remove(&id).await?;
+    info!("Terminated tus upload {}", id);
+
+    Ok((StatusCode::NO_CONTENT, tus_headers()).into_response())
+}
+
+/// Create the video row for a completed upload and queue it for processing,
+/// returning the video and job IDs
+async fn finish_upload(
+    state: Arc<AppState>,
+    user: &AuthUser,
+    upload: &mut TusUpload,
+) -> Result<(String, Option<String>), AppError> {
+    let file_name = upload
+        .metadata
+        .get("filename")
+        .cloned()
+        .unwrap_or_else(|| "video.mp4".to_string());
+    let title = upload
+        .metadata
+        .get("title")
+        .filter(|t| !t.trim().is_empty())
+        .cloned()
+        .unwrap_or_else(|| {
+            Path::new(&file_name)
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("Untitled")
+                .to_string()
+        });
+    let description = upload
+        .metadata
+        .get("description")
+        .filter(|d| !d.trim().is_empty())
+        .cloned();
+
+    let profile = state
+        .encoding_profiles
+        .get(upload.metadata.get("profile").map(String::as_str))?;
+
+    let supabase = supabase::SupabaseService::for_user(state.clone(), user);
+    let video_id =
+        create_video_row(&supabase, user, &title, description.as_deref(), profile).await?;
+    info!(
+        "Upload {} complete, processing as video {}",
+        upload.id, video_id
+    );
+
+    // Record the video before queueing, since the worker removes the upload when done
+    upload.video_id = Some(video_id.clone());
+    state.tus.save(upload).await?;
+
+    match state.jobs.submit(
+        &video_id,
+        &file_name,
+        profile,
+        JobInput::TusUpload(upload.id.clone()),
+    ) {
+        Ok(job) => Ok((video_id, Some(job.id))),
+        Err(err) => {
+            // Leave the upload complete but unclaimed so the final PATCH can be retried
+            upload.video_id = None;
+            state.tus.save(upload).await?;
+            if let Err(e) = supabase.delete("videos", "id", &video_id).await {
+                error!("Failed to remove video {}: {}", video_id, e);
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Only the user who started an upload, or an admin, may continue or cancel it
+fn check_owner(user: &AuthUser, upload: &TusUpload) -> Result<(), AppError> {
+    if user.can_manage(upload.user_id.as_deref()) {
+        return Ok(());
+    }
+
+    Err(AppError::Forbidden(format!(
+        "Upload {} belongs to another user",
+        upload.id
+    )))
+}
+
+fn tus_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+    headers
+}
+
+/// Tell the client when an unfinished upload will be removed
+async fn insert_expires(state: &AppState, upload: &TusUpload, headers: &mut HeaderMap) {
+    if let Some(value) = state.tus.expires_at(upload).await.and_then(|expires_at| {
+        HeaderValue::from_str(&expires_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string()).ok()
+    }) {
+        headers.insert(UPLOAD_EXPIRES, value);
+    }
+}
+
+fn check_tus_version(headers: &HeaderMap) -> Result<(), AppError> {
+    match headers.get(TUS_RESUMABLE).and_then(|v| v.to_str().ok()) {
+        Some(TUS_VERSION) => Ok(()),
+        _ => Err(AppError::Rejected(
+            StatusCode::PRECONDITION_FAILED,
+            format!("Tus-Resumable: {} is required", TUS_VERSION),
+        )),
+    }
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Result<Option<u64>, AppError> {
+    headers
+        .get(name)
+        .map(|v| {
+            v.to_str()
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .ok_or(AppError::Validation(format!("Invalid {} header", name)))
+        })
+        .transpose()
+}
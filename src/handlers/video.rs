@@ -1,29 +1,58 @@
-use std::sync::Arc;
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    sync::Arc,
+};
 
 use anyhow::Result;
 use axum::{
     Json,
     body::Bytes,
-    extract::{Multipart, State},
+    extract::{
+        ConnectInfo, FromRequest, Multipart, Path as UrlPath, Query, Request, State,
+        multipart::{Field, MultipartError},
+    },
+    http::{HeaderMap, HeaderValue, header},
+    response::{IntoResponse, Response},
 };
 use reqwest::StatusCode;
+use serde_json::json;
+use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
 use tracing::{error, info};
 use uuid::Uuid;
 
 use crate::{
-    models::{AppState, VideoUploadResponse},
+    auth::{AuthUser, PUBLISHER_ROLES, ROLE_ADMIN},
+    config::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE, SIGNED_UPLOAD_URL_TTL_SECS},
+    encoding::EncodingProfile,
+    error::AppError,
+    handlers::storage::object_response,
+    jobs::{JobInput, set_status},
+    models::{
+        AppState, CreateVideoRequest, CreateVideoResponse, DeleteVideoReport, ListVideosQuery,
+        MediaUrls, PlaybackUrlResponse, RecordViewRequest, RecordViewResponse, SortOrder,
+        StorageDeleteFailure, Video, VideoListResponse, VideoMetadata, VideoStatus,
+        VideoUploadResponse, ViewSkipReason,
+    },
+    playback::{PlaybackQuery, SESSION_COOKIE, client_ip, media_urls},
+    storage::{ByteRange, SOURCES_PREFIX, StorageBackend},
     supabase,
     video_processor::VideoProcessor,
+    views::Viewer,
 };
 
-pub async fn raw_upload(body: Bytes) -> Result<String, (StatusCode, String)> {
+// Longest `device_id` accepted when recording a view
+const MAX_DEVICE_ID_LEN: usize = 128;
+
+pub async fn raw_upload(body: Bytes) -> Result<String, AppError> {
     info!("Received raw upload of {} bytes", body.len());
 
     // Just acknowledge receipt of the data
     Ok(format!("Successfully received {} bytes", body.len()))
 }
 
-pub async fn simple_multipart(mut multipart: Multipart) -> Result<String, (StatusCode, String)> {
+pub async fn simple_multipart(mut multipart: Multipart) -> Result<String, AppError> {
     info!("Starting simple multipart test");
 
     let mut fields_count = 0;
@@ -45,92 +74,759 @@ pub async fn simple_multipart(mut multipart: Multipart) -> Result<String, (Statu
     Ok(format!("Successfully processed {} fields", fields_count))
 }
 
+/// `GET /videos` lists ready videos. With playback signing configured they carry
+/// no media URLs, which only `POST /videos/{id}/playback` hands out.
+pub async fn list_videos(
+    State(state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    Query(query): Query<ListVideosQuery>,
+) -> Result<Json<VideoListResponse>, AppError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0);
+
+    let direction = match query.order {
+        SortOrder::Asc => "asc",
+        SortOrder::Desc => "desc",
+    };
+    // Tie-break on id so pages stay stable when sort values repeat
+    let order = format!("{}.{},id.asc", query.sort.column(), direction);
+
+    let supabase = supabase::SupabaseService::for_request(state.clone(), user.as_ref());
+    let (videos, total): (Vec<Video>, i64) = supabase
+        .query_all(
+            "videos",
+            &[
+                ("status", format!("eq.{}", VideoStatus::Ready.as_str())),
+                ("order", order),
+            ],
+            Some(supabase::page_range(offset, limit)),
+        )
+        .await?;
+
+    let next_offset = supabase::next_offset(offset, videos.len(), total);
+    let videos = videos
+        .into_iter()
+        .map(|video| video_metadata(&state, video))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Json(VideoListResponse {
+        videos,
+        total,
+        limit,
+        offset,
+        next_offset,
+    }))
+}
+
+pub async fn get_video(
+    State(state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    UrlPath(id): UrlPath<Uuid>,
+) -> Result<Json<VideoMetadata>, AppError> {
+    let supabase = supabase::SupabaseService::for_request(state.clone(), user.as_ref());
+    let video: Video = supabase
+        .query_single("videos", "id", &id.to_string())
+        .await?;
+
+    Ok(Json(video_metadata(&state, video)?))
+}
+
+/// Catalog entry for a video. RLS already decided the caller may see the row.
+/// Signed media URLs are bound to a user, so with signing configured the entry
+/// has none and players mint them through `POST /videos/{id}/playback`.
+fn video_metadata(state: &AppState, video: Video) -> Result<VideoMetadata, AppError> {
+    let urls = if state.playback.is_some() {
+        MediaUrls::default()
+    } else {
+        media_urls(&state.public_base_url, &video, None)?
+    };
+    Ok(VideoMetadata::new(video, urls))
+}
+
+/// Serve the progressive rendition from storage with byte-range support.
+/// When playback signing is configured the URL must carry a valid signature.
+pub async fn stream_video(
+    State(state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    UrlPath(id): UrlPath<Uuid>,
+    Query(playback): Query<PlaybackQuery>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let ip = client_ip(&headers, peer, state.trust_forwarded_for);
+    let video = playable_video(&state, id, &playback, user.as_ref(), ip, &headers).await?;
+    let Some(path) = &video.video_path else {
+        return Err(AppError::NotFound(format!("Video {} has no download", id)));
+    };
+
+    object_response(state.storage.as_ref(), path, &headers).await
+}
+
+/// Serve a manifest, segment or thumbnail from under the video's storage prefix.
+/// With signing configured the first path segment is the playback signature, so
+/// the relative URIs inside a manifest resolve to URLs that still carry it.
+pub async fn stream_media(
+    State(state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    UrlPath((id, path)): UrlPath<(Uuid, String)>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let (playback, file) = match &state.playback {
+        Some(_) => {
+            let (token, file) = path.split_once('/').unwrap_or((&path, ""));
+            let playback = PlaybackQuery::from_path_token(token)
+                .ok_or_else(|| AppError::Forbidden("Playback requires a signed URL".to_string()))?;
+            (playback, file)
+        }
+        None => (PlaybackQuery::default(), path.as_str()),
+    };
+    // Stay inside the video's prefix
+    if file.is_empty()
+        || file
+            .split('/')
+            .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        return Err(AppError::NotFound(format!("{} not found", file)));
+    }
+
+    let ip = client_ip(&headers, peer, state.trust_forwarded_for);
+    playable_video(&state, id, &playback, user.as_ref(), ip, &headers).await?;
+
+    let object = format!("{}/{}", id, file);
+    object_response(state.storage.as_ref(), &object, &headers).await
+}
+
+/// Check a playback request and load its video, which must be ready
+async fn playable_video(
+    state: &Arc<AppState>,
+    id: Uuid,
+    playback: &PlaybackQuery,
+    user: Option<&AuthUser>,
+    ip: IpAddr,
+    headers: &HeaderMap,
+) -> Result<Video, AppError> {
+    // A valid signature is the caller's authorization, granted when the URL was
+    // minted, so the row is read with the service role
+    let supabase = match &state.playback {
+        Some(signer) => {
+            signer.verify(&id.to_string(), playback, ip)?;
+            if let Some(bound_user) = &playback.user {
+                signer.check_viewer(bound_user, user, headers)?;
+            }
+            supabase::SupabaseService::service(state.clone())
+        }
+        None => supabase::SupabaseService::anonymous(state.clone()),
+    };
+    let video: Video = supabase
+        .query_single("videos", "id", &id.to_string())
+        .await?;
+
+    if video.status != VideoStatus::Ready {
+        return Err(AppError::Conflict(format!(
+            "Video {} has not finished processing",
+            id
+        )));
+    }
+    Ok(video)
+}
+
+/// Mint a signed, expiring playback URL for a video, bound to the signed-in caller
+pub async fn create_playback_url(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    UrlPath(id): UrlPath<Uuid>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<(HeaderMap, Json<PlaybackUrlResponse>), AppError> {
+    let Some(signer) = &state.playback else {
+        return Err(AppError::Rejected(
+            StatusCode::NOT_IMPLEMENTED,
+            "Playback URL signing is not configured".to_string(),
+        ));
+    };
+
+    // RLS decides whether the caller may see the video at all
+    let supabase = supabase::SupabaseService::for_user(state.clone(), &user);
+    let video: Video = supabase
+        .query_single("videos", "id", &id.to_string())
+        .await?;
+    let Some(video_path) = video
+        .video_path
+        .clone()
+        .filter(|_| video.status == VideoStatus::Ready)
+    else {
+        return Err(AppError::Conflict(format!(
+            "Video {} has not finished processing",
+            id
+        )));
+    };
+
+    let ip = client_ip(&headers, peer, state.trust_forwarded_for);
+    let token = signer.sign(&video.id, Some(&user.id), ip);
+    let urls = media_urls(&state.public_base_url, &video, Some(&token))?;
+    let url = urls
+        .video_url
+        .ok_or_else(|| AppError::Internal(format!("Video {} has no download URL", id)))?;
+
+    // Local and memory objects can only be read through this app, so only other
+    // backends get a direct URL
+    let storage_url = if state.storage.serves_through_app() {
+        None
+    } else {
+        Some(state.storage.signed_url(&video_path, signer.ttl).await?)
+    };
+
+    // Lets browser players that can't send the access token prove who they are
+    let mut cookie = format!(
+        "{}={}; Path=/videos; Max-Age={}; HttpOnly",
+        SESSION_COOKIE,
+        signer.session(&user.id, token.expires_at),
+        signer.ttl.as_secs()
+    );
+    if state.public_base_url.starts_with("https://") {
+        cookie.push_str("; Secure; SameSite=None");
+    } else {
+        cookie.push_str("; SameSite=Lax");
+    }
+    let mut response_headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(&cookie) {
+        response_headers.insert(header::SET_COOKIE, value);
+    }
+
+    Ok((
+        response_headers,
+        Json(PlaybackUrlResponse {
+            url,
+            stream_url: urls.stream_url,
+            dash_url: urls.dash_url,
+            thumbnail_url: urls.thumbnail_url,
+            expires_at: token.expires_at.to_rfc3339(),
+            storage_url,
+        }),
+    ))
+}
+
+/// `POST /videos/{id}/view` counts a view once the viewer has watched long enough.
+/// Repeat views by the same user, device or address within the window are ignored.
+pub async fn record_view(
+    State(state): State<Arc<AppState>>,
+    user: Option<AuthUser>,
+    UrlPath(id): UrlPath<Uuid>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<RecordViewRequest>,
+) -> Result<Json<RecordViewResponse>, AppError> {
+    if !request.watched_secs.is_finite() || request.watched_secs < 0.0 {
+        return Err(AppError::Validation(
+            "watched_secs must be a non-negative number".to_string(),
+        ));
+    }
+    let device_id = request.device_id.as_deref().map(str::trim);
+    if device_id.is_some_and(|d| d.is_empty() || d.len() > MAX_DEVICE_ID_LEN) {
+        return Err(AppError::Validation(format!(
+            "device_id must be 1 to {} characters",
+            MAX_DEVICE_ID_LEN
+        )));
+    }
+
+    // RLS decides whether the caller may see the video at all
+    let supabase = supabase::SupabaseService::for_request(state.clone(), user.as_ref());
+    let video: Video = supabase
+        .query_single("videos", "id", &id.to_string())
+        .await?;
+    if video.status != VideoStatus::Ready {
+        return Err(AppError::Conflict(format!(
+            "Video {} has not finished processing",
+            id
+        )));
+    }
+
+    if !state
+        .views
+        .meets_threshold(request.watched_secs, video.duration)
+    {
+        return Ok(Json(RecordViewResponse {
+            counted: false,
+            reason: Some(ViewSkipReason::BelowThreshold),
+        }));
+    }
+
+    // Anonymous viewers choose their own device_id, so their address is part of the key
+    let viewer = match &user {
+        Some(user) => Viewer::User(&user.id),
+        None => Viewer::Anonymous {
+            ip: client_ip(&headers, peer, state.trust_forwarded_for),
+            device_id,
+        },
+    };
+    let Some(view) = state.views.record(&video.id, &viewer) else {
+        return Ok(Json(RecordViewResponse {
+            counted: false,
+            reason: Some(ViewSkipReason::Duplicate),
+        }));
+    };
+
+    // Only the service role may increment, since deduplication happens here
+    if let Err(e) = supabase::SupabaseService::service(state.clone())
+        .call_rpc("increment_views", json!({ "video_id": video.id }))
+        .await
+    {
+        state.views.forget(&video.id, &viewer, view);
+        return Err(e);
+    }
+
+    Ok(Json(RecordViewResponse {
+        counted: true,
+        reason: None,
+    }))
+}
+
+pub async fn delete_video(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    UrlPath(id): UrlPath<Uuid>,
+) -> Result<Response, AppError> {
+    let supabase = supabase::SupabaseService::for_user(state.clone(), &user);
+    let video_id = id.to_string();
+    let video: Video = supabase.query_single("videos", "id", &video_id).await?;
+
+    // Storage isn't covered by RLS, so check ownership before removing any objects
+    if !user.can_manage(video.user_id.as_deref()) {
+        return Err(AppError::Forbidden(format!(
+            "Video {} belongs to another user",
+            video_id
+        )));
+    }
+
+    // Processed output lives under the video's own prefix
+    let mut paths = video.storage_paths();
+    for path in state.storage.list(&video_id).await? {
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+
+    let mut deleted = Vec::new();
+    let mut failed = Vec::new();
+    for path in paths {
+        match state.storage.delete(&path).await {
+            Ok(()) => deleted.push(path),
+            Err(e) => {
+                error!("Failed to delete {} for video {}: {}", path, video_id, e);
+                failed.push(StorageDeleteFailure {
+                    path,
+                    error: e.detail().to_string(),
+                });
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        let report = DeleteVideoReport {
+            id: video_id,
+            deleted,
+            failed,
+            row_deleted: false,
+        };
+        return Ok((StatusCode::BAD_GATEWAY, Json(report)).into_response());
+    }
+
+    supabase.delete("videos", "id", &video_id).await?;
+    info!(
+        "Deleted video {} and {} storage objects",
+        video_id,
+        deleted.len()
+    );
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// `POST /videos` accepts either a JSON `CreateVideoRequest`, which creates a pending
+/// row and returns a signed storage upload URL, or a multipart file upload
+pub async fn create_video(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    request: Request,
+) -> Result<Response, AppError> {
+    user.require_role(PUBLISHER_ROLES)?;
+
+    let is_json = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+
+    if is_json {
+        let Json(body) = Json::<CreateVideoRequest>::from_request(request, &state).await?;
+        return create_pending_video(state, &user, body)
+            .await
+            .map(IntoResponse::into_response);
+    }
+
+    let multipart = Multipart::from_request(request, &state).await?;
+    upload_video(State(state), user, multipart)
+        .await
+        .map(IntoResponse::into_response)
+}
+
+async fn create_pending_video(
+    state: Arc<AppState>,
+    user: &AuthUser,
+    request: CreateVideoRequest,
+) -> Result<Json<CreateVideoResponse>, AppError> {
+    if request.title.trim().is_empty() {
+        return Err(AppError::Validation("Title is required".to_string()));
+    }
+
+    // Check the profile now rather than when the upload is finalized
+    let profile = state
+        .encoding_profiles
+        .get(request.profile.as_deref())?
+        .name
+        .clone();
+    let supabase = supabase::SupabaseService::for_user(state.clone(), user);
+
+    let video_id = Uuid::new_v4().to_string();
+    let extension = request
+        .file_name
+        .as_deref()
+        .and_then(|name| Path::new(name).extension())
+        .and_then(|ext| ext.to_str())
+        .filter(|ext| ext.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or("mp4");
+    let source_path = format!("{}{}.{}", SOURCES_PREFIX, video_id, extension);
+
+    let video: Video = supabase
+        .insert(
+            "videos",
+            json!({
+                "id": video_id,
+                "title": request.title,
+                "description": request.description,
+                "source_path": source_path,
+                "encoding_profile": profile,
+                "user_id": user.id,
+                "status": VideoStatus::Pending,
+            }),
+        )
+        .await?;
+
+    let upload_url = match state.storage.signed_upload_url(&source_path).await {
+        Ok(url) => url,
+        Err(err) => {
+            // Don't leave a pending row behind that nothing can be uploaded to
+            if let Err(e) = supabase.delete("videos", "id", &video.id).await {
+                error!("Failed to remove pending video {}: {}", video.id, e);
+            }
+            return Err(err);
+        }
+    };
+
+    info!("Created pending video {} at {}", video.id, source_path);
+    Ok(Json(CreateVideoResponse {
+        id: video.id,
+        title: video.title,
+        upload_url,
+        expires_in: SIGNED_UPLOAD_URL_TTL_SECS,
+    }))
+}
+
+/// Start processing a video the client uploaded straight to storage
+pub async fn finalize_video(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    UrlPath(id): UrlPath<Uuid>,
+) -> Result<(StatusCode, Json<VideoUploadResponse>), AppError> {
+    user.require_role(PUBLISHER_ROLES)?;
+
+    let supabase = supabase::SupabaseService::for_user(state.clone(), &user);
+    let video_id = id.to_string();
+
+    // A finalize sent before the upload lands leaves the row pending, so it can be retried
+    let pending: Video = supabase.query_single("videos", "id", &video_id).await?;
+    if pending.status == VideoStatus::Pending
+        && user.can_manage(pending.user_id.as_deref())
+        && let Some(source_path) = &pending.source_path
+    {
+        check_uploaded(state.storage.as_ref(), &video_id, source_path).await?;
+    }
+
+    // Only the request that moves the row out of `pending` gets to process it
+    let mut filters = vec![
+        ("id", format!("eq.{}", video_id)),
+        ("status", format!("eq.{}", VideoStatus::Pending.as_str())),
+    ];
+    if user.role != ROLE_ADMIN {
+        filters.push(("user_id", format!("eq.{}", user.id)));
+    }
+    let mut claimed: Vec<Video> = supabase
+        .update_returning(
+            "videos",
+            &filters,
+            json!({ "status": VideoStatus::Uploaded }),
+        )
+        .await?;
+
+    let Some(video) = claimed.pop() else {
+        // Distinguish a missing video from someone else's or one already finalized
+        let existing: Video = supabase.query_single("videos", "id", &video_id).await?;
+        if !user.can_manage(existing.user_id.as_deref()) {
+            return Err(AppError::Forbidden(format!(
+                "Video {} belongs to another user",
+                video_id
+            )));
+        }
+        return Err(AppError::Conflict(format!(
+            "Video {} is not awaiting an upload",
+            video_id
+        )));
+    };
+
+    let Some(source_path) = video.source_path.clone() else {
+        set_status(&supabase, &video_id, VideoStatus::Failed).await?;
+        return Err(AppError::Conflict(format!(
+            "Video {} has no source upload",
+            video_id
+        )));
+    };
+
+    let job = match state
+        .encoding_profiles
+        .get(video.encoding_profile.as_deref())
+        .and_then(|profile| {
+            state.jobs.submit(
+                &video_id,
+                &source_path,
+                profile,
+                JobInput::StorageObject(source_path.clone()),
+            )
+        }) {
+        Ok(job) => job,
+        Err(err) => {
+            // Hand the row back so finalize can be retried
+            set_status(&supabase, &video_id, VideoStatus::Pending).await?;
+            return Err(err);
+        }
+    };
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(VideoUploadResponse {
+            id: video_id,
+            job_id: job.id,
+            status: video.status,
+            video_url: None,
+            thumbnail_url: None,
+        }),
+    ))
+}
+
+/// Fail with 409 unless the client's upload to `source_path` has arrived
+async fn check_uploaded(
+    storage: &dyn StorageBackend,
+    video_id: &str,
+    source_path: &str,
+) -> Result<(), AppError> {
+    match storage
+        .get_range(source_path, Some(ByteRange::Suffix(1)))
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(AppError::NotFound(_)) => Err(AppError::Conflict(format!(
+            "The upload for video {} has not arrived yet; finalize once it has finished",
+            video_id
+        ))),
+        // Only an empty object has no last byte
+        Err(AppError::Rejected(StatusCode::RANGE_NOT_SATISFIABLE, _)) => Err(AppError::Conflict(
+            format!("The upload for video {} is empty", video_id),
+        )),
+        Err(e) => Err(e),
+    }
+}
+
 pub async fn upload_video(
     State(state): State<Arc<AppState>>,
+    user: AuthUser,
     mut multipart: Multipart,
-) -> Result<Json<VideoUploadResponse>, (StatusCode, String)> {
+) -> Result<(StatusCode, Json<VideoUploadResponse>), AppError> {
     info!("Starting to process multipart upload");
 
+    let mut title: String = String::default();
+    let mut description: Option<String> = None;
     let mut file_name: String = String::default();
+    let mut profile: Option<String> = None;
     let mut _content_type: String = String::default();
-    let mut content: Bytes = Bytes::default();
+    let mut upload: Option<(NamedTempFile, u64)> = None;
 
-    // Handle the next_field result properly
-    while let Ok(Some(field)) = multipart.next_field().await {
-        if let Some(name) = field.name() {
-            info!("Processing field: {}", name);
-            if name == "file" {
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        let Some(name) = field.name().map(str::to_string) else {
+            continue;
+        };
+        info!("Processing field: {}", name);
+
+        match name.as_str() {
+            "title" => title = read_text_field(field).await?,
+            "description" => {
+                let text = read_text_field(field).await?;
+                description = (!text.trim().is_empty()).then_some(text);
+            }
+            "profile" => {
+                let text = read_text_field(field).await?;
+                profile = (!text.trim().is_empty()).then_some(text);
+            }
+            "file" | "video" => {
                 file_name = field.file_name().unwrap_or("video.mp4").to_string();
                 _content_type = field.content_type().unwrap_or("video/mp4").to_string();
                 info!("File name: {}, Content-Type: {}", file_name, _content_type);
 
-                // Handle bytes result properly
-                content = match field.bytes().await {
-                    Ok(bytes) => {
-                        info!("Successfully read {} bytes", bytes.len());
-                        bytes
-                    }
-                    Err(err) => {
-                        error!("Failed to read file bytes: {}", err);
-                        return Err((
-                            StatusCode::BAD_REQUEST,
-                            format!("Failed to read file bytes: {}", err),
-                        ));
-                    }
-                };
+                let (temp_file, size) = stream_field_to_file(field, state.max_upload_bytes).await?;
+                info!(
+                    "Successfully wrote {} bytes to {:?}",
+                    size,
+                    temp_file.path()
+                );
+                upload = Some((temp_file, size));
             }
+            _ => {}
         }
     }
 
-    if content.is_empty() {
+    let Some((input_file, _)) = upload.filter(|(_, size)| *size > 0) else {
         error!("No file content found");
-        return Err((StatusCode::BAD_REQUEST, "No file content found".to_string()));
+        return Err(AppError::Validation("No file content found".to_string()));
+    };
+
+    if title.trim().is_empty() {
+        title = Path::new(&file_name)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("Untitled")
+            .to_string();
     }
 
-    // Process the video with FFmpeg
-    info!("Processing video: {} ({} bytes)", file_name, content.len());
-    let (output_path, filename) = VideoProcessor::process_video(&content, &file_name).await?;
+    let profile = state.encoding_profiles.get(profile.as_deref())?;
+
+    // Turn away files that aren't usable video while the client is still waiting
+    VideoProcessor::inspect(input_file.path(), state.max_video_duration_secs).await?;
 
     // Create Supabase service
-    let supabase = supabase::SupabaseService::new(state.clone()).await?;
+    let supabase = supabase::SupabaseService::for_user(state.clone(), &user);
 
-    // Get the processed video data
-    info!("Trying to read processed video from: {}", &output_path);
-    if tokio::fs::try_exists(&output_path).await.unwrap_or(false) {
-        info!("File exists at path");
-    } else {
-        error!("File does not exist at path: {}", &output_path);
-    }
-    let processed_video_data = tokio::fs::read(&output_path).await.map_err(|e| {
-        error!("Failed to read processed video: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to read processed video: {}", e),
-        )
-    })?;
+    let video_id =
+        create_video_row(&supabase, &user, &title, description.as_deref(), profile).await?;
+
+    let job = match state.jobs.submit(
+        &video_id,
+        &file_name,
+        profile,
+        JobInput::TempFile(input_file.into_temp_path()),
+    ) {
+        Ok(job) => job,
+        Err(err) => {
+            // Nothing will ever process this row, so don't keep it
+            if let Err(e) = supabase.delete("videos", "id", &video_id).await {
+                error!("Failed to remove video {}: {}", video_id, e);
+            }
+            return Err(err);
+        }
+    };
 
-    info!("Uploading processed video to storage");
-    // Upload the processed video to Supabase
-    supabase
-        .upload_file_with_content_type(
-            &filename,
-            processed_video_data,
-            "video/mp4",
-            "937f5714-ab0b-471b-a66d-d07e3b68af70",
+    info!("Video {} queued for processing as job {}", video_id, job.id);
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(VideoUploadResponse {
+            id: video_id,
+            job_id: job.id,
+            status: VideoStatus::Uploaded,
+            video_url: None,
+            thumbnail_url: None,
+        }),
+    ))
+}
+
+/// Record a received upload before processing so it can be tracked
+pub(crate) async fn create_video_row(
+    supabase: &supabase::SupabaseService,
+    user: &AuthUser,
+    title: &str,
+    description: Option<&str>,
+    profile: &EncodingProfile,
+) -> Result<String, AppError> {
+    let video: Video = supabase
+        .insert(
+            "videos",
+            json!({
+                "title": title,
+                "description": description,
+                "encoding_profile": profile.name,
+                "user_id": user.id,
+                "status": VideoStatus::Uploaded,
+            }),
         )
         .await?;
+    info!("Created video row: {}", video.id);
 
-    // Get the public URLs
-    let video_url = supabase.get_public_url(&filename);
+    Ok(video.id)
+}
 
-    // Generate a unique ID for this video
-    let video_id = Uuid::new_v4().to_string();
+/// Pipe a multipart file field into a temporary file chunk by chunk, rejecting
+/// it once more than `max_bytes` have been received
+async fn stream_field_to_file(
+    mut field: Field<'_>,
+    max_bytes: u64,
+) -> Result<(NamedTempFile, u64), AppError> {
+    let temp_file = NamedTempFile::new().map_err(|e| {
+        error!("Failed to create temp file: {}", e);
+        AppError::Internal("Failed to create temporary file".to_string())
+    })?;
 
-    info!("Video upload complete. ID: {}", video_id);
-    // Return the response
-    Ok(Json(VideoUploadResponse {
-        id: video_id,
-        video_url,
-        thumbnail_url: Some("thumbnail_url".to_string()),
-    }))
+    let mut file = tokio::fs::File::create(temp_file.path())
+        .await
+        .map_err(|e| {
+            error!("Failed to open temp file: {}", e);
+            AppError::Internal("Failed to open temporary file".to_string())
+        })?;
+
+    let mut written: u64 = 0;
+    while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+        written += chunk.len() as u64;
+        if written > max_bytes {
+            return Err(upload_too_large(max_bytes));
+        }
+
+        file.write_all(&chunk).await.map_err(|e| {
+            error!("Failed to write to temp file: {}", e);
+            AppError::Internal("Failed to write to temporary file".to_string())
+        })?;
+    }
+
+    file.flush().await.map_err(|e| {
+        error!("Failed to flush temp file: {}", e);
+        AppError::Internal("Failed to write to temporary file".to_string())
+    })?;
+
+    Ok((temp_file, written))
+}
+
+fn upload_too_large(max_bytes: u64) -> AppError {
+    AppError::Rejected(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!("Upload exceeds the maximum size of {} bytes", max_bytes),
+    )
+}
+
+fn multipart_error(err: MultipartError) -> AppError {
+    error!("Failed to read multipart body: {}", err);
+    err.into()
+}
+
+async fn read_text_field(field: Field<'_>) -> Result<String, AppError> {
+    field
+        .text()
+        .await
+        .map_err(|e| AppError::Validation(format!("Failed to read form field: {}", e)))
 }
@@ -0,0 +1,406 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use axum::http::StatusCode;
+use futures_util::StreamExt;
+use serde::Serialize;
+use serde_json::json;
+use tempfile::{NamedTempFile, TempDir, TempPath};
+use tokio::sync::{Mutex, mpsc};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    encoding::EncodingProfile,
+    error::AppError,
+    models::{AppState, VideoStatus},
+    storage,
+    supabase::SupabaseService,
+    video_processor::{THUMBNAIL_FILE, VideoProcessor},
+};
+
+// Finished jobs stay queryable for this long
+const JOB_RETENTION: Duration = Duration::from_secs(60 * 60);
+// Storage uploads in flight per job
+const UPLOAD_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Status of a transcoding job as reported by `GET /jobs/{id}`
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: String,
+    pub video_id: String,
+    pub state: JobState,
+    // Percent complete, from 0 to 100
+    pub progress: f32,
+    pub error: Option<String>,
+    #[serde(skip)]
+    finished_at: Option<Instant>,
+}
+
+/// Where a job reads the uploaded video from
+pub enum JobInput {
+    // A local temp file, removed once the job is done
+    TempFile(TempPath),
+    // A completed tus upload, released from the upload store once the job is done
+    TusUpload(String),
+    // An object the client uploaded straight to storage
+    StorageObject(String),
+}
+
+struct TranscodeTask {
+    job_id: String,
+    video_id: String,
+    file_name: String,
+    profile: EncodingProfile,
+    input: JobInput,
+}
+
+/// Bounded queue of transcoding work drained by a fixed pool of workers
+pub struct JobQueue {
+    jobs: RwLock<HashMap<String, Job>>,
+    sender: mpsc::Sender<TranscodeTask>,
+    receiver: Mutex<mpsc::Receiver<TranscodeTask>>,
+    workers: usize,
+}
+
+impl JobQueue {
+    pub fn new(workers: usize, capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+
+        Self {
+            jobs: RwLock::new(HashMap::new()),
+            sender,
+            receiver: Mutex::new(receiver),
+            workers: workers.max(1),
+        }
+    }
+
+    /// Queue a video for processing, failing with 503 when the queue is full
+    pub fn submit(
+        &self,
+        video_id: &str,
+        file_name: &str,
+        profile: &EncodingProfile,
+        input: JobInput,
+    ) -> Result<Job, AppError> {
+        let job = Job {
+            id: Uuid::new_v4().to_string(),
+            video_id: video_id.to_string(),
+            state: JobState::Queued,
+            progress: 0.0,
+            error: None,
+            finished_at: None,
+        };
+
+        if let Ok(mut jobs) = self.jobs.write() {
+            jobs.retain(|_, job| {
+                job.finished_at
+                    .is_none_or(|finished| finished.elapsed() < JOB_RETENTION)
+            });
+            jobs.insert(job.id.clone(), job.clone());
+        }
+
+        let task = TranscodeTask {
+            job_id: job.id.clone(),
+            video_id: video_id.to_string(),
+            file_name: file_name.to_string(),
+            profile: profile.clone(),
+            input,
+        };
+
+        if self.sender.try_send(task).is_err() {
+            if let Ok(mut jobs) = self.jobs.write() {
+                jobs.remove(&job.id);
+            }
+            return Err(AppError::Rejected(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Transcoding queue is full, try again later".to_string(),
+            ));
+        }
+
+        info!("Queued job {} for video {}", job.id, video_id);
+        Ok(job)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.jobs.read().ok()?.get(id).cloned()
+    }
+
+    fn update(&self, id: &str, f: impl FnOnce(&mut Job)) {
+        if let Ok(mut jobs) = self.jobs.write()
+            && let Some(job) = jobs.get_mut(id)
+        {
+            f(job);
+        }
+    }
+
+    fn finish(&self, id: &str, result: Result<(), String>) {
+        self.update(id, |job| {
+            match result {
+                Ok(()) => {
+                    job.state = JobState::Completed;
+                    job.progress = 100.0;
+                }
+                Err(error) => {
+                    job.state = JobState::Failed;
+                    job.error = Some(error);
+                }
+            }
+            job.finished_at = Some(Instant::now());
+        });
+    }
+}
+
+/// Lets the pipeline report how far along a job is
+pub struct JobProgress<'a> {
+    queue: &'a JobQueue,
+    job_id: &'a str,
+}
+
+impl JobProgress<'_> {
+    pub fn set(&self, percent: f32) {
+        self.queue
+            .update(self.job_id, |job| job.progress = percent.clamp(0.0, 100.0));
+    }
+}
+
+/// Spawn the worker pool. Each worker handles one job at a time.
+pub fn start_workers(state: Arc<AppState>) {
+    info!("Starting {} transcoding workers", state.jobs.workers);
+
+    for _ in 0..state.jobs.workers {
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                let task = state.jobs.receiver.lock().await.recv().await;
+                let Some(task) = task else {
+                    break;
+                };
+                run_task(&state, task).await;
+            }
+        });
+    }
+}
+
+async fn run_task(state: &Arc<AppState>, task: TranscodeTask) {
+    let supabase = SupabaseService::service(state.clone());
+    let progress = JobProgress {
+        queue: &state.jobs,
+        job_id: &task.job_id,
+    };
+    state
+        .jobs
+        .update(&task.job_id, |job| job.state = JobState::Running);
+
+    match execute(state, &supabase, &task, &progress).await {
+        Ok(()) => {
+            info!("Job {} completed for video {}", task.job_id, task.video_id);
+            state.jobs.finish(&task.job_id, Ok(()));
+        }
+        Err(e) => {
+            error!(
+                "Job {} failed for video {}: {}",
+                task.job_id, task.video_id, e
+            );
+            // Job errors reach clients, so internal messages stay in the log.
+            // The ffmpeg stderr tail is the one detail worth passing on.
+            let reason = match &e {
+                AppError::Transcode(message) => message.as_str(),
+                _ => e.detail(),
+            };
+            state.jobs.finish(&task.job_id, Err(reason.to_string()));
+            if let Err(e) = set_status(&supabase, &task.video_id, VideoStatus::Failed).await {
+                error!("Failed to mark video {} as failed: {}", task.video_id, e);
+            }
+        }
+    }
+
+    if let JobInput::TusUpload(upload_id) = &task.input
+        && let Err(e) = state.tus.remove(upload_id).await
+    {
+        error!("Failed to clean up upload {}: {}", upload_id, e);
+    }
+}
+
+/// Resolve the job input to a local file and run it through the pipeline
+async fn execute(
+    state: &AppState,
+    supabase: &SupabaseService,
+    task: &TranscodeTask,
+    progress: &JobProgress<'_>,
+) -> Result<(), AppError> {
+    set_status(supabase, &task.video_id, VideoStatus::Processing).await?;
+
+    match &task.input {
+        JobInput::TempFile(path) => process_and_store(state, supabase, task, path, progress).await,
+        JobInput::TusUpload(upload_id) => {
+            let path = state.tus.data_path(upload_id);
+            process_and_store(state, supabase, task, &path, progress).await
+        }
+        JobInput::StorageObject(source_path) => {
+            let input_file = NamedTempFile::new().map_err(|e| {
+                error!("Failed to create temp file: {}", e);
+                AppError::Internal("Failed to create temporary file".to_string())
+            })?;
+
+            let size = storage::download_to_file(
+                state.storage.as_ref(),
+                source_path,
+                input_file.path(),
+                state.max_upload_bytes,
+            )
+            .await?;
+            info!("Downloaded {} ({} bytes)", source_path, size);
+
+            process_and_store(state, supabase, task, input_file.path(), progress).await
+        }
+    }
+}
+
+/// Transcode the upload, push it to storage and mark the row as ready
+async fn process_and_store(
+    state: &AppState,
+    supabase: &SupabaseService,
+    task: &TranscodeTask,
+    input_path: &Path,
+    progress: &JobProgress<'_>,
+) -> Result<(), AppError> {
+    let video_id = task.video_id.as_str();
+    let work_dir = TempDir::new().map_err(|e| {
+        error!("Failed to create work directory: {}", e);
+        AppError::Internal("Failed to create temporary directory".to_string())
+    })?;
+
+    // Reject anything ffmpeg can't turn into a stream before spending time on it
+    let media = VideoProcessor::inspect(input_path, state.max_video_duration_secs).await?;
+    let probed = serde_json::to_value(&media).map_err(|e| AppError::Internal(e.to_string()))?;
+    supabase.update("videos", "id", video_id, probed).await?;
+
+    // Process the video with FFmpeg
+    info!("Processing video: {}", task.file_name);
+    // Encoding takes the bulk of the job, so it covers 5-70% of the progress
+    progress.set(5.0);
+    let processed = VideoProcessor::process_video(
+        input_path,
+        &media,
+        work_dir.path(),
+        &task.profile,
+        |percent| progress.set(5.0 + percent * 0.65),
+    )
+    .await?;
+    progress.set(70.0);
+
+    // Everything for a video lives under its own prefix so it can be removed together
+    let video_path = object_path(video_id, work_dir.path(), &processed.video_file)?;
+    let thumbnail_path = format!("{}/{}", video_id, THUMBNAIL_FILE);
+    let mut uploads = vec![
+        (processed.video_file.clone(), video_path.clone()),
+        (processed.thumbnail_file.clone(), thumbnail_path.clone()),
+    ];
+    for file in list_dir_recursive(&processed.stream_dir).await? {
+        let object = object_path(video_id, work_dir.path(), &file)?;
+        uploads.push((file, object));
+    }
+
+    info!(
+        "Uploading {} files for video {} to storage",
+        uploads.len(),
+        video_id
+    );
+    // Uploads cover 70-95% of the progress
+    let total = uploads.len();
+    let mut done = 0;
+    let mut results = futures_util::stream::iter(uploads)
+        .map(|(file, object)| async move {
+            let content_type = storage::content_type_for(&object);
+            storage::put_file(state.storage.as_ref(), &object, &file, content_type).await
+        })
+        .buffer_unordered(UPLOAD_CONCURRENCY);
+    while let Some(result) = results.next().await {
+        result?;
+        done += 1;
+        progress.set(70.0 + 25.0 * done as f32 / total as f32);
+    }
+
+    let stream_path = processed
+        .hls_master
+        .map(|path| object_path(video_id, work_dir.path(), &path))
+        .transpose()?;
+    let dash_path = processed
+        .dash_manifest
+        .map(|path| object_path(video_id, work_dir.path(), &path))
+        .transpose()?;
+
+    // Only paths are recorded; thumbnail and media URLs are built, and signed,
+    // whenever the row is read
+    supabase
+        .update(
+            "videos",
+            "id",
+            video_id,
+            json!({
+                "video_path": video_path,
+                "thumbnail_path": thumbnail_path,
+                "stream_path": stream_path,
+                "dash_path": dash_path,
+                "status": VideoStatus::Ready,
+            }),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Storage path for a file in the work directory, under the video's prefix
+fn object_path(video_id: &str, work_dir: &Path, file: &Path) -> Result<String, AppError> {
+    let relative = file
+        .strip_prefix(work_dir)
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    Ok(format!("{}/{}", video_id, relative.to_string_lossy()))
+}
+
+/// Every file below `dir`
+async fn list_dir_recursive(dir: &Path) -> Result<Vec<PathBuf>, AppError> {
+    let io_error = |e: std::io::Error| {
+        error!("Failed to read output directory: {}", e);
+        AppError::Internal("Failed to read processed output".to_string())
+    };
+
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await.map_err(io_error)?;
+        while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+            if entry.file_type().await.map_err(io_error)?.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+pub async fn set_status(
+    supabase: &SupabaseService,
+    video_id: &str,
+    status: VideoStatus,
+) -> Result<(), AppError> {
+    supabase
+        .update("videos", "id", video_id, json!({ "status": status }))
+        .await
+}
@@ -1,7 +1,10 @@
 use anyhow::Result;
 use axum::{
-    Router, // routing::{get, post},
-    routing::post,
+    Router,
+    extract::DefaultBodyLimit,
+    http::HeaderName,
+    middleware,
+    routing::{get, patch, post, put},
 };
 use reqwest::Method;
 use std::net::SocketAddr;
@@ -10,11 +13,19 @@ use tower_http::cors::{Any, CorsLayer};
 use tracing::{Level, info};
 use tracing_subscriber::FmtSubscriber;
 
+pub mod auth;
 pub mod config;
-pub mod handler;
+pub mod encoding;
+pub mod error;
+pub mod handlers;
+pub mod jobs;
 pub mod models;
+pub mod playback;
+pub mod storage;
 pub mod supabase;
+pub mod tus;
 pub mod video_processor;
+pub mod views;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -28,25 +39,126 @@ async fn main() -> Result<()> {
     let state = load_config;
     info!("Configuration loaded successfully");
 
+    jobs::start_workers(state.clone());
+    tus::start_sweeper(state.tus.clone());
+
     // Enhanced CORS middleware
     let cors = CorsLayer::new()
         .allow_origin(Any)
-        .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
-        .allow_headers(Any);
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::HEAD,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .allow_headers(Any)
+        .expose_headers([
+            HeaderName::from_static("location"),
+            HeaderName::from_static("accept-ranges"),
+            HeaderName::from_static("content-range"),
+            HeaderName::from_static("content-length"),
+            HeaderName::from_static("etag"),
+            HeaderName::from_static("tus-resumable"),
+            HeaderName::from_static("tus-version"),
+            HeaderName::from_static("tus-extension"),
+            HeaderName::from_static("tus-max-size"),
+            HeaderName::from_static("upload-offset"),
+            HeaderName::from_static("upload-length"),
+            HeaderName::from_static("upload-metadata"),
+            HeaderName::from_static("upload-expires"),
+            HeaderName::from_static("x-video-id"),
+            HeaderName::from_static("x-job-id"),
+            HeaderName::from_static(error::REQUEST_ID_HEADER),
+        ]);
 
     info!("Setting up routes");
 
+    // Uploads are streamed to disk, so only cap the body at the configured file size
+    let upload_body_limit =
+        DefaultBodyLimit::max((state.max_upload_bytes + config::MULTIPART_OVERHEAD_BYTES) as usize);
+
     // Build the application router
-    let app = Router::new()
-        .route("/videos", post(handler::upload_video))
-        .route("/shows", post(handler::create_show))
-        // .route(
-        //     "/videos/{id}",
-        //     get(videos::get_video).delete(videos::delete_video),
-        // )
-        // // .route("/videos/{id}/upload", post(videos::upload_video))
-        // .route("/videos/{id}/stream", get(videos::stream_video))
+    let mut app = Router::new()
+        .route(
+            "/videos",
+            get(handlers::video::list_videos)
+                .post(handlers::video::create_video)
+                .layer(upload_body_limit),
+        )
+        .route(
+            "/videos/{id}",
+            get(handlers::video::get_video).delete(handlers::video::delete_video),
+        )
+        .route(
+            "/videos/{id}/finalize",
+            post(handlers::video::finalize_video),
+        )
+        .route("/videos/{id}/stream", get(handlers::video::stream_video))
+        .route(
+            "/videos/{id}/media/{*path}",
+            get(handlers::video::stream_media),
+        )
+        .route(
+            "/videos/{id}/playback",
+            post(handlers::video::create_playback_url),
+        )
+        .route("/videos/{id}/view", post(handlers::video::record_view))
+        .route("/jobs/{id}", get(handlers::job::get_job))
+        .route("/uploads", post(handlers::tus::create_upload))
+        .route(
+            "/uploads/{id}",
+            get(handlers::tus::upload_offset)
+                .patch(handlers::tus::append_upload)
+                .delete(handlers::tus::terminate_upload),
+        )
+        .route(
+            "/genres",
+            get(handlers::genre::list_genres).post(handlers::genre::create_genre),
+        )
+        .route(
+            "/genres/{slug}",
+            get(handlers::genre::get_genre)
+                .patch(handlers::genre::update_genre)
+                .delete(handlers::genre::delete_genre),
+        )
+        .route(
+            "/shows",
+            get(handlers::show::list_shows).post(handlers::show::create_show),
+        )
+        .route(
+            "/shows/{id}",
+            get(handlers::show::get_show)
+                .patch(handlers::show::update_show)
+                .delete(handlers::show::delete_show),
+        )
+        .route(
+            "/shows/{id}/episodes",
+            get(handlers::episode::list_episodes).post(handlers::episode::attach_episode),
+        )
+        .route(
+            "/shows/{id}/episodes/order",
+            put(handlers::episode::reorder_episodes),
+        )
+        .route(
+            "/shows/{id}/episodes/{episode_id}",
+            patch(handlers::episode::update_episode).delete(handlers::episode::detach_episode),
+        );
+
+    // Stored objects are only served unsigned when playback signing is off
+    if state.playback.is_none() {
+        app = app.route("/storage/{*path}", get(handlers::storage::serve_object));
+    }
+
+    let app = app
         .layer(cors)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            handlers::tus::tus_protocol,
+        ))
+        .layer(middleware::from_fn(error::request_id))
         .with_state(state);
 
     // Start the server
@@ -58,7 +170,12 @@ async fn main() -> Result<()> {
     info!("Server listening on {}", addr);
 
     // await the serve call instead of using the ? operator
-    axum::serve(listener, app).await?;
+    // Peer addresses are needed to bind playback URLs to a client IP
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
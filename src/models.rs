@@ -1,12 +1,65 @@
 use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use uuid::Uuid;
+
+use crate::{
+    auth::JwtVerifier, encoding::EncodingProfiles, jobs::JobQueue, playback::PlaybackSigner,
+    storage::StorageBackend, tus::TusStore, views::ViewTracker,
+};
+
+/// Lifecycle of a row in the `videos` table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoStatus {
+    // Created through `POST /videos` and waiting for the client to upload to storage
+    Pending,
+    Uploaded,
+    Processing,
+    Ready,
+    Failed,
+}
+
+impl VideoStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VideoStatus::Pending => "pending",
+            VideoStatus::Uploaded => "uploaded",
+            VideoStatus::Processing => "processing",
+            VideoStatus::Ready => "ready",
+            VideoStatus::Failed => "failed",
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Video {
     pub id: String,
     pub title: String,
     pub description: Option<String>,
-    pub video_url: String,
-    pub thumbnail_url: Option<String>,
+    // Object paths inside the storage bucket
+    pub source_path: Option<String>,
+    pub video_path: Option<String>,
+    pub thumbnail_path: Option<String>,
+    // HLS master playlist and DASH manifest
+    pub stream_path: Option<String>,
+    pub dash_path: Option<String>,
+    pub status: VideoStatus,
+    // The user who uploaded the video
+    #[serde(default)]
+    pub user_id: Option<String>,
+    // Name of the encoding profile the upload asked for
+    pub encoding_profile: Option<String>,
+    // Probed from the upload before transcoding
+    pub container: Option<String>,
+    pub duration: Option<f64>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub rotation: Option<i32>,
+    pub frame_rate: Option<f64>,
+    pub audio_channels: Option<i32>,
+    pub bit_rate: Option<i64>,
     pub created_at: String,
     #[serde(default)]
     pub likes: i32,
@@ -14,10 +67,33 @@ pub struct Video {
     pub views: i32,
 }
 
+impl Video {
+    /// Every storage object that belongs to this video
+    pub fn storage_paths(&self) -> Vec<String> {
+        [
+            &self.source_path,
+            &self.video_path,
+            &self.thumbnail_path,
+            &self.stream_path,
+            &self.dash_path,
+        ]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateVideoRequest {
     pub title: String,
     pub description: Option<String>,
+    // Original name of the file that will be uploaded, used for its extension
+    #[serde(default)]
+    pub file_name: Option<String>,
+    // Encoding profile to process the video with, the server default when absent
+    #[serde(default)]
+    pub profile: Option<String>,
 }
 
 // Ensure this exactly matches what the frontend expects
@@ -25,7 +101,9 @@ pub struct CreateVideoRequest {
 pub struct CreateVideoResponse {
     pub id: String,
     pub title: String,
+    // Signed storage URL the client PUTs the file to before calling finalize
     pub upload_url: String,
+    pub expires_in: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -33,16 +111,200 @@ pub struct VideoMetadata {
     pub id: String,
     pub title: String,
     pub description: Option<String>,
-    pub stream_url: String,
+    // HLS master playlist, or the progressive MP4 for videos processed before HLS
+    pub stream_url: Option<String>,
+    pub dash_url: Option<String>,
+    pub video_url: Option<String>,
     pub thumbnail_url: Option<String>,
+    pub status: VideoStatus,
+    // Seconds, known once the upload has been probed
+    pub duration: Option<f64>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
     pub created_at: String,
+    pub likes: i32,
+    pub views: i32,
+}
+
+/// Where a player fetches a video's media from. Every URL points at this app,
+/// which checks the playback signature before reading storage.
+#[derive(Debug, Default)]
+pub struct MediaUrls {
+    pub video_url: Option<String>,
+    pub stream_url: Option<String>,
+    pub dash_url: Option<String>,
+    pub thumbnail_url: Option<String>,
+}
+
+impl VideoMetadata {
+    pub fn new(video: Video, urls: MediaUrls) -> Self {
+        Self {
+            id: video.id,
+            title: video.title,
+            description: video.description,
+            stream_url: urls.stream_url,
+            dash_url: urls.dash_url,
+            video_url: urls.video_url,
+            thumbnail_url: urls.thumbnail_url,
+            status: video.status,
+            duration: video.duration,
+            width: video.width,
+            height: video.height,
+            created_at: video.created_at,
+            likes: video.likes,
+            views: video.views,
+        }
+    }
+}
+
+/// Body of `POST /videos/{id}/view`
+#[derive(Debug, Deserialize)]
+pub struct RecordViewRequest {
+    // How much of the video the viewer has watched, in seconds
+    pub watched_secs: f64,
+    // Stable per-install identifier, used to deduplicate anonymous viewers
+    #[serde(default)]
+    pub device_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViewSkipReason {
+    BelowThreshold,
+    Duplicate,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecordViewResponse {
+    pub counted: bool,
+    // Why the view wasn't counted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<ViewSkipReason>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlaybackUrlResponse {
+    // Signed `GET /videos/{id}/stream` URL
+    pub url: String,
+    // Signed HLS master playlist and DASH manifest, when the video has them
+    pub stream_url: Option<String>,
+    pub dash_url: Option<String>,
+    // Signed thumbnail, when the video has one
+    pub thumbnail_url: Option<String>,
+    pub expires_at: String,
+    // Signed URL straight to the object, for backends with their own signing
+    pub storage_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VideoSort {
+    #[default]
+    CreatedAt,
+    Views,
+    Likes,
+}
+
+impl VideoSort {
+    pub fn column(&self) -> &'static str {
+        match self {
+            VideoSort::CreatedAt => "created_at",
+            VideoSort::Views => "views",
+            VideoSort::Likes => "likes",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Query parameters accepted by `GET /videos`
+#[derive(Debug, Deserialize)]
+pub struct ListVideosQuery {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    #[serde(default)]
+    pub sort: VideoSort,
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VideoListResponse {
+    pub videos: Vec<VideoMetadata>,
+    pub total: i64,
+    pub limit: usize,
+    pub offset: usize,
+    // Offset of the next page, absent on the last page
+    pub next_offset: Option<usize>,
 }
 
 #[derive(Clone)]
 pub struct AppState {
     pub supabase_url: String,
+    // Service role key, which bypasses row-level security
     pub supabase_api_key: String,
-    pub supabase_bucket: String,
+    // Public anon key, used for anonymous requests and as the `apikey` for user requests
+    pub supabase_anon_key: String,
+    pub storage: Arc<dyn StorageBackend>,
+    // Verifies Supabase access tokens; without it every authenticated request is refused
+    pub auth: Option<Arc<JwtVerifier>>,
+    // Address clients reach this service on
+    pub public_base_url: String,
+    // When set, `GET /videos/{id}/stream` only accepts signed URLs
+    pub playback: Option<Arc<PlaybackSigner>>,
+    // Take the client IP from `X-Forwarded-For`, for running behind a proxy
+    pub trust_forwarded_for: bool,
+    // Largest video file accepted by the upload endpoint
+    pub max_upload_bytes: u64,
+    // Longer videos are rejected when probed
+    pub max_video_duration_secs: u64,
+    pub tus: Arc<TusStore>,
+    pub jobs: Arc<JobQueue>,
+    pub encoding_profiles: Arc<EncodingProfiles>,
+    pub views: Arc<ViewTracker>,
+}
+
+/// Where a show is in its run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShowStatus {
+    Announced,
+    Ongoing,
+    Completed,
+    Hiatus,
+    Cancelled,
+}
+
+impl ShowStatus {
+    pub const ALL: [ShowStatus; 5] = [
+        ShowStatus::Announced,
+        ShowStatus::Ongoing,
+        ShowStatus::Completed,
+        ShowStatus::Hiatus,
+        ShowStatus::Cancelled,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShowStatus::Announced => "announced",
+            ShowStatus::Ongoing => "ongoing",
+            ShowStatus::Completed => "completed",
+            ShowStatus::Hiatus => "hiatus",
+            ShowStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ShowStatus> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str() == value)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -53,25 +315,236 @@ pub struct Show {
     pub description: String,
     pub release_date: String, // in ISO format: YYYY-MM-DD
     pub thumbnail_url: String,
+    // Derived from the show's published episodes, so never written directly
+    #[serde(default)]
     pub episode_count: i32,
-    pub genre: String,
+    // Genre slugs
+    pub genres: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
     pub rating: f32,
-    pub status: String,
+    pub status: ShowStatus,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created_at: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub updated_at: Option<String>,
 }
 
+/// Body of `POST /shows`. Fields are checked together so every problem is
+/// reported at once, which is why missing ones fall back to empty values here.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CreateShowRequest {
+    pub title: String,
+    pub description: String,
+    // YYYY-MM-DD
+    pub release_date: String,
+    pub thumbnail_url: String,
+    pub genres: Vec<String>,
+    pub tags: Vec<String>,
+    pub rating: Option<f32>,
+    pub status: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateShowResponse {
     pub id: String,
     pub title: String,
 }
 
+/// Query parameters accepted by `GET /shows`
+#[derive(Debug, Deserialize)]
+pub struct ListShowsQuery {
+    // Genre slug
+    pub genre: Option<String>,
+    pub tag: Option<String>,
+    pub status: Option<ShowStatus>,
+    pub min_rating: Option<f32>,
+    // Inclusive bounds on `release_date`, as YYYY-MM-DD
+    pub released_from: Option<String>,
+    pub released_to: Option<String>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShowListResponse {
+    pub shows: Vec<Show>,
+    pub total: i64,
+    pub limit: usize,
+    pub offset: usize,
+    // Offset of the next page, absent on the last page
+    pub next_offset: Option<usize>,
+}
+
+/// Body of `PATCH /shows/{id}`. Only the fields present are changed.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateShowRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub genres: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rating: Option<f32>,
+    // Checked against `ShowStatus` with the other fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
+/// A row in the `genres` table, also the body of `POST /genres`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Genre {
+    pub slug: String,
+    pub display_name: String,
+    // Locale code to display name
+    #[serde(default)]
+    pub localized_names: HashMap<String, String>,
+    #[serde(default)]
+    pub sort_order: i32,
+    #[serde(default = "default_true")]
+    pub active: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Body of `PATCH /genres/{slug}`. The slug itself can't change.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateGenreRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub localized_names: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort_order: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+}
+
+/// Query parameters accepted by `GET /genres`
+#[derive(Debug, Deserialize)]
+pub struct ListGenresQuery {
+    // Only honoured for publishers
+    #[serde(default)]
+    pub include_inactive: bool,
+}
+
+/// Whether an episode is visible to viewers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EpisodeStatus {
+    #[default]
+    Draft,
+    Published,
+}
+
+impl EpisodeStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EpisodeStatus::Draft => "draft",
+            EpisodeStatus::Published => "published",
+        }
+    }
+}
+
+/// A video attached to a show at a season and episode number
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Episode {
+    pub id: String,
+    pub show_id: String,
+    pub video_id: String,
+    pub season: i32,
+    pub episode_number: i32,
+    pub title: String,
+    pub synopsis: Option<String>,
+    // Seconds
+    pub duration: Option<f64>,
+    pub status: EpisodeStatus,
+    pub published_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Body of `POST /shows/{id}/episodes`
+#[derive(Debug, Deserialize)]
+pub struct AttachEpisodeRequest {
+    pub video_id: Uuid,
+    pub season: i32,
+    pub episode_number: i32,
+    // The video's title when absent
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub synopsis: Option<String>,
+    #[serde(default)]
+    pub status: EpisodeStatus,
+}
+
+/// Body of `PATCH /shows/{id}/episodes/{episode_id}`. Only the fields present are changed.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateEpisodeRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub synopsis: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<EpisodeStatus>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EpisodePosition {
+    pub id: Uuid,
+    pub season: i32,
+    pub episode_number: i32,
+}
+
+/// Body of `PUT /shows/{id}/episodes/order`. Episodes left out keep their place.
+#[derive(Debug, Deserialize)]
+pub struct ReorderEpisodesRequest {
+    pub episodes: Vec<EpisodePosition>,
+}
+
+/// Returned once an upload has been accepted and queued for processing
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VideoUploadResponse {
     pub id: String,
-    pub video_url: String,
+    // Poll `GET /jobs/{job_id}` for processing progress
+    pub job_id: String,
+    pub status: VideoStatus,
+    // Always `None`: nothing has been processed yet, and media URLs are built
+    // from the stored paths when the video is read
+    pub video_url: Option<String>,
     pub thumbnail_url: Option<String>,
 }
+
+#[derive(Debug, Serialize)]
+pub struct StorageDeleteFailure {
+    pub path: String,
+    pub error: String,
+}
+
+/// Returned by `DELETE /videos/{id}` when some storage objects could not be removed
+#[derive(Debug, Serialize)]
+pub struct DeleteVideoReport {
+    pub id: String,
+    pub deleted: Vec<String>,
+    pub failed: Vec<StorageDeleteFailure>,
+    // The row is kept until every object is gone so the delete can be retried
+    pub row_deleted: bool,
+}
@@ -0,0 +1,552 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use axum::http::{HeaderMap, header};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use reqwest::Url;
+
+use crate::{
+    auth::AuthUser,
+    error::AppError,
+    models::{MediaUrls, Video, VideoStatus},
+};
+
+// Set when a bound URL is minted, so players that can't send an Authorization
+// header can still show which user they are playing for
+pub const SESSION_COOKIE: &str = "playback_session";
+
+/// Signature parameters on a playback URL
+#[derive(Debug, Default, Deserialize)]
+pub struct PlaybackQuery {
+    // Unix timestamp after which the URL stops working
+    pub expires: Option<i64>,
+    pub user: Option<String>,
+    // Hex HMAC-SHA256 over the video, expiry, user and (optionally) client IP
+    pub sig: Option<String>,
+}
+
+impl PlaybackQuery {
+    /// Parse the `{expires}.{sig}` or `{expires}.{user}.{sig}` path segment of a media URL
+    pub fn from_path_token(token: &str) -> Option<Self> {
+        let (expires, rest) = token.split_once('.')?;
+        let (user, signature) = match rest.rsplit_once('.') {
+            Some((user, signature)) => (Some(user.to_string()), signature),
+            None => (None, rest),
+        };
+
+        Some(Self {
+            expires: Some(expires.parse().ok()?),
+            user,
+            sig: Some(signature.to_string()),
+        })
+    }
+}
+
+/// A signature that makes a video's playback URLs valid until `expires_at`
+pub struct PlaybackToken {
+    pub expires_at: DateTime<Utc>,
+    user: Option<String>,
+    signature: String,
+}
+
+impl PlaybackToken {
+    /// Query parameters for a `/stream` URL
+    pub fn params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![("expires", self.expires_at.timestamp().to_string())];
+        if let Some(user) = &self.user {
+            params.push(("user", user.clone()));
+        }
+        params.push(("sig", self.signature.clone()));
+        params
+    }
+
+    /// The same signature as a single path segment, for media URLs. Players
+    /// resolve a manifest's relative segment URIs against it, so they keep it.
+    pub fn path_token(&self) -> String {
+        match &self.user {
+            Some(user) => format!(
+                "{}.{}.{}",
+                self.expires_at.timestamp(),
+                user,
+                self.signature
+            ),
+            None => format!("{}.{}", self.expires_at.timestamp(), self.signature),
+        }
+    }
+}
+
+/// Signs and verifies playback URLs so private videos can't be hotlinked.
+/// Tokens are stateless: anything holding the secret can check them.
+pub struct PlaybackSigner {
+    secret: Vec<u8>,
+    pub ttl: Duration,
+    // Tie URLs to the address they were issued to
+    pub bind_ip: bool,
+}
+
+impl PlaybackSigner {
+    pub fn new(secret: impl Into<Vec<u8>>, ttl: Duration, bind_ip: bool) -> Self {
+        Self {
+            secret: secret.into(),
+            ttl,
+            bind_ip,
+        }
+    }
+
+    /// Sign access to a video for `ttl`, optionally for a single user
+    pub fn sign(&self, video_id: &str, user: Option<&str>, ip: IpAddr) -> PlaybackToken {
+        let expires_at = Utc::now() + self.ttl;
+        let expires = expires_at.timestamp();
+        let signature = hex::encode(
+            self.url_mac(video_id, expires, user, ip)
+                .finalize()
+                .into_bytes(),
+        );
+
+        PlaybackToken {
+            expires_at,
+            user: user.map(str::to_string),
+            signature,
+        }
+    }
+
+    /// Check a playback URL's signature and expiry
+    pub fn verify(
+        &self,
+        video_id: &str,
+        query: &PlaybackQuery,
+        ip: IpAddr,
+    ) -> Result<(), AppError> {
+        let (Some(expires), Some(signature)) = (query.expires, &query.sig) else {
+            return Err(AppError::Forbidden(
+                "Playback requires a signed URL".to_string(),
+            ));
+        };
+
+        let signature = hex::decode(signature).unwrap_or_default();
+        // Constant-time comparison, so signatures can't be guessed byte by byte
+        self.url_mac(video_id, expires, query.user.as_deref(), ip)
+            .verify_slice(&signature)
+            .map_err(|_| AppError::Forbidden("Invalid playback signature".to_string()))?;
+
+        if expires < Utc::now().timestamp() {
+            return Err(AppError::Forbidden("Playback URL has expired".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Session cookie value vouching for `user` until `expires_at`
+    pub fn session(&self, user: &str, expires_at: DateTime<Utc>) -> String {
+        let expires = expires_at.timestamp();
+        let signature = hex::encode(self.session_mac(user, expires).finalize().into_bytes());
+        format!("{}.{}.{}", expires, user, signature)
+    }
+
+    /// Check that the caller is the user a playback URL is bound to, either by
+    /// their access token or by the session cookie set when the URL was minted
+    pub fn check_viewer(
+        &self,
+        bound_user: &str,
+        user: Option<&AuthUser>,
+        headers: &HeaderMap,
+    ) -> Result<(), AppError> {
+        let session_user = session_cookie(headers).and_then(|value| self.verify_session(value));
+        if user.is_some_and(|user| user.id == bound_user)
+            || session_user.as_deref() == Some(bound_user)
+        {
+            return Ok(());
+        }
+
+        if user.is_none() && session_user.is_none() {
+            return Err(AppError::Unauthorized(
+                "This playback URL needs the signed-in user it was issued to".to_string(),
+            ));
+        }
+        Err(AppError::Forbidden(
+            "This playback URL was issued to another user".to_string(),
+        ))
+    }
+
+    /// The user a session cookie vouches for, if it is genuine and unexpired
+    fn verify_session(&self, value: &str) -> Option<String> {
+        let (expires, rest) = value.split_once('.')?;
+        let (user, signature) = rest.rsplit_once('.')?;
+        let expires: i64 = expires.parse().ok()?;
+
+        let signature = hex::decode(signature).ok()?;
+        self.session_mac(user, expires)
+            .verify_slice(&signature)
+            .ok()?;
+
+        (expires >= Utc::now().timestamp()).then(|| user.to_string())
+    }
+
+    fn url_mac(
+        &self,
+        video_id: &str,
+        expires: i64,
+        user: Option<&str>,
+        ip: IpAddr,
+    ) -> Hmac<Sha256> {
+        let ip = if self.bind_ip {
+            ip.to_string()
+        } else {
+            String::new()
+        };
+        self.mac(&format!(
+            "{}\n{}\n{}\n{}",
+            video_id,
+            expires,
+            user.unwrap_or(""),
+            ip
+        ))
+    }
+
+    // Prefixed so a session signature can never pass for a URL signature
+    fn session_mac(&self, user: &str, expires: i64) -> Hmac<Sha256> {
+        self.mac(&format!("session\n{}\n{}", user, expires))
+    }
+
+    fn mac(&self, message: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(message.as_bytes());
+        mac
+    }
+}
+
+/// App URLs for a ready video's media, carrying `token` when playback signing is
+/// configured. The progressive download goes through `/stream`, and manifests,
+/// segments and the thumbnail through `/media`.
+pub fn media_urls(
+    public_base_url: &str,
+    video: &Video,
+    token: Option<&PlaybackToken>,
+) -> Result<MediaUrls, AppError> {
+    if video.status != VideoStatus::Ready {
+        return Ok(MediaUrls::default());
+    }
+
+    let base = Url::parse(&format!("{}/videos/{}", public_base_url, video.id))
+        .map_err(|e| AppError::Internal(format!("Invalid PUBLIC_BASE_URL: {}", e)))?;
+
+    let video_url = video.video_path.as_ref().map(|_| {
+        let mut url = base.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.push("stream");
+        }
+        if let Some(token) = token {
+            url.query_pairs_mut().extend_pairs(token.params());
+        }
+        url.to_string()
+    });
+    // Only objects under the video's own prefix are served as media
+    let prefix = format!("{}/", video.id);
+    let media_url = |path: &Option<String>| {
+        let file = path.as_deref()?.strip_prefix(&prefix)?;
+        let mut url = base.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.push("media");
+            if let Some(token) = token {
+                segments.push(&token.path_token());
+            }
+            segments.extend(file.split('/'));
+        }
+        Some(url.to_string())
+    };
+
+    Ok(MediaUrls {
+        stream_url: media_url(&video.stream_path).or_else(|| video_url.clone()),
+        dash_url: media_url(&video.dash_path),
+        thumbnail_url: media_url(&video.thumbnail_path),
+        video_url,
+    })
+}
+
+/// The client's address, taken from `X-Forwarded-For` when running behind a proxy
+pub fn client_ip(headers: &HeaderMap, peer: SocketAddr, trust_forwarded_for: bool) -> IpAddr {
+    if trust_forwarded_for
+        && let Some(ip) = headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .and_then(|v| v.trim().parse().ok())
+    {
+        return ip;
+    }
+
+    peer.ip()
+}
+
+/// The value of the playback session cookie, if the request carries one
+fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use axum::http::HeaderValue;
+    use serde_json::Value;
+
+    use super::*;
+
+    const VIDEO: &str = "11111111-1111-1111-1111-111111111111";
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+
+    fn signer(bind_ip: bool) -> PlaybackSigner {
+        PlaybackSigner::new("secret", Duration::from_secs(600), bind_ip)
+    }
+
+    fn query(token: &PlaybackToken) -> PlaybackQuery {
+        PlaybackQuery {
+            expires: Some(token.expires_at.timestamp()),
+            user: token.user.clone(),
+            sig: Some(token.signature.clone()),
+        }
+    }
+
+    fn user(id: &str) -> AuthUser {
+        AuthUser {
+            id: id.to_string(),
+            role: "authenticated".to_string(),
+            claims: Value::Null,
+            access_token: String::new(),
+        }
+    }
+
+    fn cookie(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_str(&format!("theme=dark; {}={}", SESSION_COOKIE, value)).unwrap(),
+        );
+        headers
+    }
+
+    fn is_forbidden(result: Result<(), AppError>) -> bool {
+        matches!(result, Err(AppError::Forbidden(_)))
+    }
+
+    #[test]
+    fn signed_url_verifies() {
+        let signer = signer(false);
+        let token = signer.sign(VIDEO, Some("alice"), CLIENT);
+
+        assert!(signer.verify(VIDEO, &query(&token), CLIENT).is_ok());
+    }
+
+    #[test]
+    fn expired_url_is_rejected() {
+        let signer = signer(false);
+        let expires = Utc::now().timestamp() - 1;
+        let signature = hex::encode(
+            signer
+                .url_mac(VIDEO, expires, None, CLIENT)
+                .finalize()
+                .into_bytes(),
+        );
+        let query = PlaybackQuery {
+            expires: Some(expires),
+            user: None,
+            sig: Some(signature),
+        };
+
+        assert!(is_forbidden(signer.verify(VIDEO, &query, CLIENT)));
+    }
+
+    #[test]
+    fn tampered_url_is_rejected() {
+        let signer = signer(false);
+        let token = signer.sign(VIDEO, Some("alice"), CLIENT);
+
+        let mut other_user = query(&token);
+        other_user.user = Some("mallory".to_string());
+        assert!(is_forbidden(signer.verify(VIDEO, &other_user, CLIENT)));
+
+        let mut unbound = query(&token);
+        unbound.user = None;
+        assert!(is_forbidden(signer.verify(VIDEO, &unbound, CLIENT)));
+
+        let mut later = query(&token);
+        later.expires = later.expires.map(|expires| expires + 3600);
+        assert!(is_forbidden(signer.verify(VIDEO, &later, CLIENT)));
+
+        let mut flipped = query(&token);
+        flipped.sig = flipped.sig.map(|sig| {
+            let last = if sig.ends_with('0') { "1" } else { "0" };
+            format!("{}{}", &sig[..sig.len() - 1], last)
+        });
+        assert!(is_forbidden(signer.verify(VIDEO, &flipped, CLIENT)));
+
+        let other_video = "22222222-2222-2222-2222-222222222222";
+        assert!(is_forbidden(signer.verify(
+            other_video,
+            &query(&token),
+            CLIENT
+        )));
+
+        let missing = PlaybackQuery::default();
+        assert!(is_forbidden(signer.verify(VIDEO, &missing, CLIENT)));
+    }
+
+    #[test]
+    fn other_secret_is_rejected() {
+        let token =
+            PlaybackSigner::new("other", Duration::from_secs(600), false).sign(VIDEO, None, CLIENT);
+
+        assert!(is_forbidden(signer(false).verify(
+            VIDEO,
+            &query(&token),
+            CLIENT
+        )));
+    }
+
+    #[test]
+    fn ip_binding() {
+        let other = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1));
+
+        let bound = signer(true);
+        let token = bound.sign(VIDEO, None, CLIENT);
+        assert!(bound.verify(VIDEO, &query(&token), CLIENT).is_ok());
+        assert!(is_forbidden(bound.verify(VIDEO, &query(&token), other)));
+
+        let unbound = signer(false);
+        let token = unbound.sign(VIDEO, None, CLIENT);
+        assert!(unbound.verify(VIDEO, &query(&token), other).is_ok());
+    }
+
+    #[test]
+    fn session_mac_is_not_a_url_mac() {
+        let signer = signer(false);
+        let expires_at = Utc::now() + signer.ttl;
+        let session = signer.session("alice", expires_at);
+        let (_, signature) = session.rsplit_once('.').unwrap();
+
+        let query = PlaybackQuery {
+            expires: Some(expires_at.timestamp()),
+            user: Some("alice".to_string()),
+            sig: Some(signature.to_string()),
+        };
+        assert!(is_forbidden(signer.verify(VIDEO, &query, CLIENT)));
+    }
+
+    #[test]
+    fn url_mac_is_not_a_session_mac() {
+        let signer = signer(false);
+        let token = signer.sign(VIDEO, Some("alice"), CLIENT);
+        let value = format!("{}.alice.{}", token.expires_at.timestamp(), token.signature);
+
+        assert_eq!(signer.verify_session(&value), None);
+    }
+
+    #[test]
+    fn session_verifies_until_it_expires() {
+        let signer = signer(false);
+
+        let current = signer.session("alice", Utc::now() + signer.ttl);
+        assert_eq!(signer.verify_session(&current).as_deref(), Some("alice"));
+
+        let expired = signer.session("alice", Utc::now() - chrono::Duration::seconds(1));
+        assert_eq!(signer.verify_session(&expired), None);
+
+        let forged = current.replacen("alice", "mallory", 1);
+        assert_eq!(signer.verify_session(&forged), None);
+    }
+
+    #[test]
+    fn viewer_must_match_bound_user() {
+        let signer = signer(false);
+        let none = HeaderMap::new();
+
+        assert!(
+            signer
+                .check_viewer("alice", Some(&user("alice")), &none)
+                .is_ok()
+        );
+        assert!(is_forbidden(signer.check_viewer(
+            "alice",
+            Some(&user("mallory")),
+            &none
+        )));
+        assert!(matches!(
+            signer.check_viewer("alice", None, &none),
+            Err(AppError::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn viewer_session_cookie() {
+        let signer = signer(false);
+        let expires_at = Utc::now() + signer.ttl;
+
+        let alice = cookie(&signer.session("alice", expires_at));
+        assert!(signer.check_viewer("alice", None, &alice).is_ok());
+
+        let mallory = cookie(&signer.session("mallory", expires_at));
+        assert!(is_forbidden(signer.check_viewer("alice", None, &mallory)));
+
+        let forged = cookie(
+            &signer
+                .session("alice", expires_at)
+                .replacen("alice", "mal", 1),
+        );
+        assert!(matches!(
+            signer.check_viewer("alice", None, &forged),
+            Err(AppError::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn path_token_without_user() {
+        let query = PlaybackQuery::from_path_token("1767225600.abcdef").unwrap();
+
+        assert_eq!(query.expires, Some(1767225600));
+        assert_eq!(query.user, None);
+        assert_eq!(query.sig.as_deref(), Some("abcdef"));
+    }
+
+    #[test]
+    fn path_token_with_dotted_user() {
+        let query =
+            PlaybackQuery::from_path_token("1767225600.first.last@example.com.abcdef").unwrap();
+
+        assert_eq!(query.expires, Some(1767225600));
+        assert_eq!(query.user.as_deref(), Some("first.last@example.com"));
+        assert_eq!(query.sig.as_deref(), Some("abcdef"));
+    }
+
+    #[test]
+    fn path_token_rejects_malformed() {
+        assert!(PlaybackQuery::from_path_token("1767225600").is_none());
+        assert!(PlaybackQuery::from_path_token("soon.abcdef").is_none());
+        assert!(PlaybackQuery::from_path_token("").is_none());
+    }
+
+    #[test]
+    fn path_token_round_trips() {
+        let signer = signer(false);
+        for user in [None, Some("first.last")] {
+            let token = signer.sign(VIDEO, user, CLIENT);
+            let query = PlaybackQuery::from_path_token(&token.path_token()).unwrap();
+
+            assert_eq!(query.user.as_deref(), user);
+            assert!(signer.verify(VIDEO, &query, CLIENT).is_ok());
+        }
+    }
+}
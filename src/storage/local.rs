@@ -0,0 +1,215 @@
+use std::{
+    io::SeekFrom,
+    path::{Component, Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::StreamExt;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use super::{
+    ByteRange, ByteStream, ObjectBody, StorageBackend, content_type_for, io_error, not_found,
+    range_not_satisfiable,
+};
+
+use crate::error::AppError;
+
+/// Keeps objects as files under a root directory and serves them through the app
+pub struct LocalStorage {
+    root: PathBuf,
+    base_url: String,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>, base_url: impl Into<String>) -> std::io::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+
+        Ok(Self {
+            root,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Map an object path onto the root, refusing anything that would escape it
+    fn file_path(&self, path: &str) -> Result<PathBuf, AppError> {
+        let relative = Path::new(path);
+        let is_safe = relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+        if !is_safe || path.is_empty() {
+            return Err(AppError::Validation(format!(
+                "Invalid storage path {:?}",
+                path
+            )));
+        }
+
+        Ok(self.root.join(relative))
+    }
+
+    /// Write through a temp file so readers never see a half-written object
+    async fn write(&self, path: &str, mut stream: ByteStream) -> Result<(), AppError> {
+        let destination = self.file_path(path)?;
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).await.map_err(io_error)?;
+        }
+
+        let file_name = destination
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let partial =
+            destination.with_file_name(format!(".{}.{}.partial", file_name, Uuid::new_v4()));
+        let mut file = fs::File::create(&partial).await.map_err(io_error)?;
+        let mut result = Ok(());
+        while let Some(chunk) = stream.next().await {
+            result = match chunk {
+                Ok(chunk) => file.write_all(&chunk).await,
+                Err(e) => Err(e),
+            };
+            if result.is_err() {
+                break;
+            }
+        }
+        if let Err(e) = result.and(file.flush().await) {
+            let _ = fs::remove_file(&partial).await;
+            return Err(io_error(e));
+        }
+
+        fs::rename(&partial, &destination).await.map_err(io_error)
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put(&self, path: &str, content: Bytes, _content_type: &str) -> Result<(), AppError> {
+        self.write(
+            path,
+            Box::pin(futures_util::stream::once(async move { Ok(content) })),
+        )
+        .await
+    }
+
+    async fn put_stream(
+        &self,
+        path: &str,
+        stream: ByteStream,
+        _length: u64,
+        _content_type: &str,
+    ) -> Result<(), AppError> {
+        self.write(path, stream).await
+    }
+
+    async fn get_range(
+        &self,
+        path: &str,
+        range: Option<ByteRange>,
+    ) -> Result<ObjectBody, AppError> {
+        let file_path = self.file_path(path)?;
+        let mut file = match fs::File::open(&file_path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(not_found(path)),
+            Err(e) => return Err(io_error(e)),
+        };
+
+        let metadata = file.metadata().await.map_err(io_error)?;
+        if metadata.is_dir() {
+            return Err(not_found(path));
+        }
+        let total_size = metadata.len();
+
+        let (start, end) = match range {
+            Some(range) => range
+                .resolve(total_size)
+                .ok_or_else(|| range_not_satisfiable(path))?,
+            None => (0, total_size.saturating_sub(1)),
+        };
+
+        file.seek(SeekFrom::Start(start)).await.map_err(io_error)?;
+        let length = if total_size == 0 { 0 } else { end - start + 1 };
+
+        Ok(ObjectBody {
+            stream: Box::pin(ReaderStream::new(file.take(length))),
+            start,
+            end,
+            total_size,
+            content_type: content_type_for(path).to_string(),
+            etag: Some(file_etag(&metadata)),
+        })
+    }
+
+    async fn etag(&self, path: &str) -> Result<Option<String>, AppError> {
+        match fs::metadata(self.file_path(path)?).await {
+            Ok(metadata) if !metadata.is_dir() => Ok(Some(file_etag(&metadata))),
+            Ok(_) => Err(not_found(path)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(not_found(path)),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    async fn delete(&self, path: &str) -> Result<(), AppError> {
+        match fs::remove_file(self.file_path(path)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, AppError> {
+        let prefix = prefix.trim_end_matches('/');
+        let mut files = Vec::new();
+        let mut folders = vec![prefix.to_string()];
+
+        while let Some(folder) = folders.pop() {
+            let mut entries = match fs::read_dir(self.file_path(&folder)?).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(io_error(e)),
+            };
+
+            while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+                // Skip objects that are still being written
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+                let path = format!("{}/{}", folder, entry.file_name().to_string_lossy());
+                if entry.file_type().await.map_err(io_error)?.is_dir() {
+                    folders.push(path);
+                } else {
+                    files.push(path);
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn public_url(&self, path: &str) -> String {
+        format!("{}/storage/{}", self.base_url, path)
+    }
+
+    fn serves_through_app(&self) -> bool {
+        true
+    }
+}
+
+/// Size and modification time, which change whenever the file is rewritten
+fn file_etag(metadata: &std::fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos());
+    format!("\"{:x}-{:x}\"", metadata.len(), modified)
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures_util::StreamExt;
+
+use super::{ByteRange, ByteStream, ObjectBody, StorageBackend, not_found, range_not_satisfiable};
+
+use crate::error::AppError;
+
+struct MemoryObject {
+    content: Bytes,
+    content_type: String,
+    // Taken from a store-wide counter so ETags change when an object is
+    // replaced, including after it was deleted in between
+    version: u64,
+}
+
+/// Keeps objects in a map, for tests and running without any storage service.
+/// Everything is lost when the process exits.
+pub struct MemoryStorage {
+    objects: RwLock<HashMap<String, MemoryObject>>,
+    next_version: AtomicU64,
+    base_url: String,
+}
+
+impl MemoryStorage {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            objects: RwLock::new(HashMap::new()),
+            next_version: AtomicU64::new(0),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn store(&self, path: &str, content: Bytes, content_type: &str) {
+        if let Ok(mut objects) = self.objects.write() {
+            let version = self.next_version.fetch_add(1, Ordering::Relaxed);
+            objects.insert(
+                path.to_string(),
+                MemoryObject {
+                    content,
+                    content_type: content_type.to_string(),
+                    version,
+                },
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryStorage {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn put(&self, path: &str, content: Bytes, content_type: &str) -> Result<(), AppError> {
+        self.store(path, content, content_type);
+        Ok(())
+    }
+
+    async fn put_stream(
+        &self,
+        path: &str,
+        mut stream: ByteStream,
+        length: u64,
+        content_type: &str,
+    ) -> Result<(), AppError> {
+        let mut content = BytesMut::with_capacity(length as usize);
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| AppError::Internal(e.to_string()))?;
+            content.extend_from_slice(&chunk);
+        }
+
+        self.store(path, content.freeze(), content_type);
+        Ok(())
+    }
+
+    async fn get_range(
+        &self,
+        path: &str,
+        range: Option<ByteRange>,
+    ) -> Result<ObjectBody, AppError> {
+        let objects = self
+            .objects
+            .read()
+            .map_err(|_| AppError::Internal("Storage lock poisoned".to_string()))?;
+        let object = objects.get(path).ok_or_else(|| not_found(path))?;
+
+        let total_size = object.content.len() as u64;
+        let (start, end) = match range {
+            Some(range) => range
+                .resolve(total_size)
+                .ok_or_else(|| range_not_satisfiable(path))?,
+            None => (0, total_size.saturating_sub(1)),
+        };
+        let content = if total_size == 0 {
+            Bytes::new()
+        } else {
+            object.content.slice(start as usize..=end as usize)
+        };
+
+        Ok(ObjectBody {
+            stream: Box::pin(futures_util::stream::once(async move { Ok(content) })),
+            start,
+            end,
+            total_size,
+            content_type: object.content_type.clone(),
+            etag: Some(object_etag(object)),
+        })
+    }
+
+    async fn etag(&self, path: &str) -> Result<Option<String>, AppError> {
+        let objects = self
+            .objects
+            .read()
+            .map_err(|_| AppError::Internal("Storage lock poisoned".to_string()))?;
+        let object = objects.get(path).ok_or_else(|| not_found(path))?;
+        Ok(Some(object_etag(object)))
+    }
+
+    async fn delete(&self, path: &str) -> Result<(), AppError> {
+        if let Ok(mut objects) = self.objects.write() {
+            objects.remove(path);
+        }
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, AppError> {
+        let prefix = format!("{}/", prefix.trim_end_matches('/'));
+        let objects = self
+            .objects
+            .read()
+            .map_err(|_| AppError::Internal("Storage lock poisoned".to_string()))?;
+
+        Ok(objects
+            .keys()
+            .filter(|path| path.starts_with(&prefix))
+            .cloned()
+            .collect())
+    }
+
+    fn public_url(&self, path: &str) -> String {
+        format!("{}/storage/{}", self.base_url, path)
+    }
+
+    fn serves_through_app(&self) -> bool {
+        true
+    }
+}
+
+/// Size and version, which change whenever the object is replaced
+fn object_etag(object: &MemoryObject) -> String {
+    format!("\"{:x}-{:x}\"", object.content.len(), object.version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn etag_changes_when_object_is_replaced() {
+        let storage = MemoryStorage::new("http://localhost");
+        storage
+            .put("a/b", Bytes::from_static(b"one"), "text/plain")
+            .await
+            .unwrap();
+        let first = storage.etag("a/b").await.unwrap();
+
+        storage
+            .put("a/b", Bytes::from_static(b"two"), "text/plain")
+            .await
+            .unwrap();
+        let second = storage.etag("a/b").await.unwrap();
+        assert_ne!(first, second);
+
+        storage.delete("a/b").await.unwrap();
+        storage
+            .put("a/b", Bytes::from_static(b"one"), "text/plain")
+            .await
+            .unwrap();
+        let third = storage.etag("a/b").await.unwrap();
+        assert_ne!(first, third);
+        assert_ne!(second, third);
+    }
+}
@@ -0,0 +1,357 @@
+use std::{path::Path, pin::Pin, time::Duration};
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+
+use crate::error::AppError;
+
+pub mod local;
+pub mod memory;
+pub mod s3;
+pub mod supabase;
+
+pub use local::LocalStorage;
+pub use memory::MemoryStorage;
+pub use s3::{S3Config, S3Storage};
+pub use supabase::SupabaseStorage;
+
+/// Where raw uploads are kept until they're transcoded
+pub const SOURCES_PREFIX: &str = "sources/";
+
+/// Object bodies are streamed rather than buffered so large renditions never sit in memory
+pub type ByteStream = Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>;
+
+/// A `Range: bytes=...` request against a stored object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    // `start-`
+    From(u64),
+    // `start-end`, inclusive
+    Inclusive(u64, u64),
+    // `-length`, the last `length` bytes
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Parse a single-range `Range` header. Multiple ranges aren't supported.
+    pub fn parse(header: &str) -> Option<ByteRange> {
+        let spec = header.trim().strip_prefix("bytes=")?;
+        if spec.contains(',') {
+            return None;
+        }
+
+        let (start, end) = spec.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+        match (start.is_empty(), end.is_empty()) {
+            (true, false) => end.parse().ok().map(ByteRange::Suffix),
+            (false, true) => start.parse().ok().map(ByteRange::From),
+            (false, false) => {
+                let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+                (start <= end).then_some(ByteRange::Inclusive(start, end))
+            }
+            (true, true) => None,
+        }
+    }
+
+    pub fn to_header(self) -> String {
+        match self {
+            ByteRange::From(start) => format!("bytes={}-", start),
+            ByteRange::Inclusive(start, end) => format!("bytes={}-{}", start, end),
+            ByteRange::Suffix(length) => format!("bytes=-{}", length),
+        }
+    }
+
+    /// The inclusive byte offsets this range covers in an object of `size` bytes,
+    /// or `None` when it can't be satisfied
+    pub fn resolve(self, size: u64) -> Option<(u64, u64)> {
+        if size == 0 {
+            return None;
+        }
+
+        match self {
+            ByteRange::From(start) => (start < size).then_some((start, size - 1)),
+            ByteRange::Inclusive(start, end) => {
+                (start < size).then_some((start, end.min(size - 1)))
+            }
+            ByteRange::Suffix(0) => None,
+            ByteRange::Suffix(length) => Some((size.saturating_sub(length), size - 1)),
+        }
+    }
+}
+
+/// The requested part of a stored object
+pub struct ObjectBody {
+    pub stream: ByteStream,
+    // Inclusive offsets of the bytes in `stream`
+    pub start: u64,
+    pub end: u64,
+    pub total_size: u64,
+    pub content_type: String,
+    pub etag: Option<String>,
+}
+
+impl ObjectBody {
+    pub fn content_length(&self) -> u64 {
+        if self.total_size == 0 {
+            0
+        } else {
+            self.end - self.start + 1
+        }
+    }
+}
+
+/// Where uploads and processed output are kept
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Short name for logs and error messages
+    fn name(&self) -> &'static str;
+
+    async fn put(&self, path: &str, content: Bytes, content_type: &str) -> Result<(), AppError>;
+
+    /// Store `length` bytes read from `stream`, replacing any existing object
+    async fn put_stream(
+        &self,
+        path: &str,
+        stream: ByteStream,
+        length: u64,
+        content_type: &str,
+    ) -> Result<(), AppError>;
+
+    /// Read an object, or part of one. Fails with 404 when it doesn't exist and
+    /// 416 when the range lies outside it.
+    async fn get_range(&self, path: &str, range: Option<ByteRange>)
+    -> Result<ObjectBody, AppError>;
+
+    /// The object's current entity tag, without reading its content. Fails with
+    /// 404 when it doesn't exist.
+    async fn etag(&self, path: &str) -> Result<Option<String>, AppError> {
+        // The last byte carries the same headers as the whole object
+        match self.get_range(path, Some(ByteRange::Suffix(1))).await {
+            Ok(object) => Ok(object.etag),
+            // Only an empty object has no last byte
+            Err(AppError::Rejected(StatusCode::RANGE_NOT_SATISFIABLE, _)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Remove an object. One that is already gone counts as deleted.
+    async fn delete(&self, path: &str) -> Result<(), AppError>;
+
+    /// Every object stored under `prefix`, including nested folders
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, AppError>;
+
+    fn public_url(&self, path: &str) -> String;
+
+    /// A URL that grants read access to the object for `ttl`, for backends that
+    /// can sign their own URLs
+    async fn signed_url(&self, _path: &str, _ttl: Duration) -> Result<String, AppError> {
+        Err(AppError::Rejected(
+            StatusCode::NOT_IMPLEMENTED,
+            format!(
+                "The {} storage backend can't sign URLs; use a playback URL instead",
+                self.name()
+            ),
+        ))
+    }
+
+    /// A URL the client can upload an object to directly, for backends that support it
+    async fn signed_upload_url(&self, _path: &str) -> Result<String, AppError> {
+        Err(AppError::Rejected(
+            StatusCode::NOT_IMPLEMENTED,
+            format!(
+                "The {} storage backend does not support direct uploads",
+                self.name()
+            ),
+        ))
+    }
+
+    /// Whether objects can only be read through this app. Without playback signing
+    /// they are also served under `/storage/{path}`.
+    fn serves_through_app(&self) -> bool {
+        false
+    }
+}
+
+/// Upload a local file
+pub async fn put_file(
+    storage: &dyn StorageBackend,
+    path: &str,
+    file: &Path,
+    content_type: &str,
+) -> Result<(), AppError> {
+    let file = tokio::fs::File::open(file).await.map_err(io_error)?;
+    let length = file.metadata().await.map_err(io_error)?.len();
+
+    storage
+        .put_stream(
+            path,
+            Box::pin(ReaderStream::new(file)),
+            length,
+            content_type,
+        )
+        .await
+}
+
+/// Stream a stored object into a local file, returning its size
+pub async fn download_to_file(
+    storage: &dyn StorageBackend,
+    path: &str,
+    destination: &Path,
+    max_bytes: u64,
+) -> Result<u64, AppError> {
+    let mut object = storage.get_range(path, None).await?;
+    if object.total_size > max_bytes {
+        return Err(too_large(max_bytes));
+    }
+
+    let mut file = tokio::fs::File::create(destination)
+        .await
+        .map_err(io_error)?;
+    let mut written: u64 = 0;
+
+    while let Some(chunk) = object.stream.next().await {
+        let chunk = chunk.map_err(|e| AppError::UpstreamStorage(e.to_string()))?;
+        written += chunk.len() as u64;
+        if written > max_bytes {
+            return Err(too_large(max_bytes));
+        }
+        file.write_all(&chunk).await.map_err(io_error)?;
+    }
+
+    file.flush().await.map_err(io_error)?;
+    Ok(written)
+}
+
+/// Content type for an object, going by its extension
+pub fn content_type_for(path: &str) -> &'static str {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("m3u8") => "application/vnd.apple.mpegurl",
+        Some("mpd") => "application/dash+xml",
+        Some("m4s") => "video/iso.segment",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("jpg") => "image/jpeg",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Parse a `Content-Range: bytes start-end/total` response header
+fn parse_content_range(value: &str) -> Option<(u64, u64, u64)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    Some((start.parse().ok()?, end.parse().ok()?, total.parse().ok()?))
+}
+
+fn too_large(max_bytes: u64) -> AppError {
+    AppError::Rejected(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!("Upload exceeds the maximum size of {} bytes", max_bytes),
+    )
+}
+
+fn not_found(path: &str) -> AppError {
+    AppError::NotFound(format!("{} not found in storage", path))
+}
+
+fn range_not_satisfiable(path: &str) -> AppError {
+    AppError::Rejected(
+        StatusCode::RANGE_NOT_SATISFIABLE,
+        format!("Requested range is outside {}", path),
+    )
+}
+
+fn io_error(e: std::io::Error) -> AppError {
+    tracing::error!("Storage I/O error: {}", e);
+    AppError::Internal("Storage I/O error".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_single_ranges() {
+        assert_eq!(
+            ByteRange::parse("bytes=0-499"),
+            Some(ByteRange::Inclusive(0, 499))
+        );
+        assert_eq!(ByteRange::parse("bytes=500-"), Some(ByteRange::From(500)));
+        assert_eq!(ByteRange::parse("bytes=-500"), Some(ByteRange::Suffix(500)));
+        assert_eq!(
+            ByteRange::parse(" bytes= 10 - 20 "),
+            Some(ByteRange::Inclusive(10, 20))
+        );
+    }
+
+    #[test]
+    fn parse_rejects_multiple_ranges() {
+        assert_eq!(ByteRange::parse("bytes=0-1,5-6"), None);
+        assert_eq!(ByteRange::parse("bytes=0-1, -5"), None);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_ranges() {
+        for header in [
+            "bytes=",
+            "bytes=-",
+            "bytes=5-1",
+            "bytes=a-b",
+            "bytes=1-b",
+            "items=0-1",
+            "0-1",
+        ] {
+            assert_eq!(ByteRange::parse(header), None, "{}", header);
+        }
+    }
+
+    #[test]
+    fn header_round_trips() {
+        for range in [
+            ByteRange::From(7),
+            ByteRange::Inclusive(1, 2),
+            ByteRange::Suffix(3),
+        ] {
+            assert_eq!(ByteRange::parse(&range.to_header()), Some(range));
+        }
+    }
+
+    #[test]
+    fn resolve_within_object() {
+        assert_eq!(ByteRange::Inclusive(0, 499).resolve(1000), Some((0, 499)));
+        assert_eq!(ByteRange::From(500).resolve(1000), Some((500, 999)));
+        assert_eq!(ByteRange::Suffix(100).resolve(1000), Some((900, 999)));
+    }
+
+    #[test]
+    fn resolve_clamps_to_object() {
+        assert_eq!(
+            ByteRange::Inclusive(900, 5000).resolve(1000),
+            Some((900, 999))
+        );
+        assert_eq!(ByteRange::Suffix(5000).resolve(1000), Some((0, 999)));
+    }
+
+    #[test]
+    fn resolve_unsatisfiable() {
+        assert_eq!(ByteRange::From(1000).resolve(1000), None);
+        assert_eq!(ByteRange::Inclusive(1000, 1001).resolve(1000), None);
+        assert_eq!(ByteRange::Suffix(0).resolve(1000), None);
+    }
+
+    #[test]
+    fn resolve_zero_length_object() {
+        assert_eq!(ByteRange::From(0).resolve(0), None);
+        assert_eq!(ByteRange::Inclusive(0, 0).resolve(0), None);
+        assert_eq!(ByteRange::Suffix(1).resolve(0), None);
+    }
+
+    #[test]
+    fn content_range_header() {
+        assert_eq!(parse_content_range("bytes 0-9/42"), Some((0, 9, 42)));
+        assert_eq!(parse_content_range("bytes */42"), None);
+    }
+}
@@ -0,0 +1,304 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use bytes::Bytes;
+use futures_util::TryStreamExt;
+use reqwest::{Client, RequestBuilder, Response, header};
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{
+    ByteRange, ByteStream, ObjectBody, StorageBackend, content_type_for, not_found,
+    parse_content_range, range_not_satisfiable,
+};
+
+use crate::error::AppError;
+
+// Largest page the storage list endpoint returns
+const STORAGE_LIST_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+struct SignedUploadUrlResponse {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct SignedUrlResponse {
+    #[serde(rename = "signedURL")]
+    signed_url: String,
+}
+
+#[derive(Deserialize)]
+struct StorageListEntry {
+    name: String,
+    // Folders are listed without an id
+    id: Option<String>,
+}
+
+/// Supabase Storage, authenticated with the service key
+pub struct SupabaseStorage {
+    client: Client,
+    url: String,
+    api_key: String,
+    bucket: String,
+}
+
+impl SupabaseStorage {
+    pub fn new(
+        url: impl Into<String>,
+        api_key: impl Into<String>,
+        bucket: impl Into<String>,
+    ) -> Self {
+        Self {
+            client: Client::new(),
+            url: url.into().trim_end_matches('/').to_string(),
+            api_key: api_key.into(),
+            bucket: bucket.into(),
+        }
+    }
+
+    fn object_url(&self, path: &str) -> String {
+        format!("{}/storage/v1/object/{}/{}", self.url, self.bucket, path)
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+            .header("apikey", &self.api_key)
+            .header(header::AUTHORIZATION, format!("Bearer {}", self.api_key))
+    }
+
+    /// Turn a failed response into an error, treating missing objects as 404.
+    /// Storage reports those as a 400 with a not_found error.
+    async fn check(
+        &self,
+        response: Response,
+        action: &str,
+        path: &str,
+    ) -> Result<Response, AppError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        if status == StatusCode::NOT_FOUND || body.contains("not_found") {
+            return Err(not_found(path));
+        }
+        Err(AppError::UpstreamStorage(format!(
+            "Failed to {} {}: {} - {}",
+            action, path, status, body
+        )))
+    }
+
+    async fn upload(
+        &self,
+        path: &str,
+        body: reqwest::Body,
+        length: u64,
+        content_type: &str,
+    ) -> Result<(), AppError> {
+        let response = self
+            .authorized(self.client.post(self.object_url(path)))
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, length)
+            // A retried job overwrites whatever an earlier attempt left behind
+            .header("x-upsert", "true")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| AppError::UpstreamStorage(e.to_string()))?;
+
+        self.check(response, "upload", path).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StorageBackend for SupabaseStorage {
+    fn name(&self) -> &'static str {
+        "supabase"
+    }
+
+    async fn put(&self, path: &str, content: Bytes, content_type: &str) -> Result<(), AppError> {
+        let length = content.len() as u64;
+        self.upload(path, content.into(), length, content_type)
+            .await
+    }
+
+    async fn put_stream(
+        &self,
+        path: &str,
+        stream: ByteStream,
+        length: u64,
+        content_type: &str,
+    ) -> Result<(), AppError> {
+        self.upload(
+            path,
+            reqwest::Body::wrap_stream(stream),
+            length,
+            content_type,
+        )
+        .await
+    }
+
+    async fn get_range(
+        &self,
+        path: &str,
+        range: Option<ByteRange>,
+    ) -> Result<ObjectBody, AppError> {
+        let mut request = self.authorized(self.client.get(self.object_url(path)));
+        if let Some(range) = range {
+            request = request.header(header::RANGE, range.to_header());
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| AppError::UpstreamStorage(e.to_string()))?;
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            return Err(range_not_satisfiable(path));
+        }
+        let response = self.check(response, "download", path).await?;
+
+        let headers = response.headers();
+        let header_str = |name| headers.get(name).and_then(|v| v.to_str().ok());
+        let content_length: u64 = header_str(header::CONTENT_LENGTH)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_default();
+
+        // A 206 carries `Content-Range: bytes start-end/total`; anything else is the whole object
+        let (start, end, total_size) = match header_str(header::CONTENT_RANGE)
+            .filter(|_| response.status() == StatusCode::PARTIAL_CONTENT)
+            .and_then(parse_content_range)
+        {
+            Some(range) => range,
+            None => (0, content_length.saturating_sub(1), content_length),
+        };
+
+        let content_type = header_str(header::CONTENT_TYPE)
+            .unwrap_or_else(|| content_type_for(path))
+            .to_string();
+        let etag = header_str(header::ETAG).map(str::to_string);
+
+        Ok(ObjectBody {
+            stream: Box::pin(response.bytes_stream().map_err(std::io::Error::other)),
+            start,
+            end,
+            total_size,
+            content_type,
+            etag,
+        })
+    }
+
+    async fn delete(&self, path: &str) -> Result<(), AppError> {
+        let response = self
+            .authorized(self.client.delete(self.object_url(path)))
+            .send()
+            .await
+            .map_err(|e| AppError::UpstreamStorage(e.to_string()))?;
+
+        // An object that is already gone counts as deleted
+        match self.check(response, "delete", path).await {
+            Ok(_) => Ok(()),
+            Err(AppError::NotFound(_)) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, AppError> {
+        let list_url = format!("{}/storage/v1/object/list/{}", self.url, self.bucket);
+
+        let mut files = Vec::new();
+        let mut folders = vec![prefix.trim_end_matches('/').to_string()];
+
+        while let Some(folder) = folders.pop() {
+            let mut offset = 0;
+            loop {
+                let response = self
+                    .authorized(self.client.post(&list_url))
+                    .json(&json!({
+                        "prefix": folder,
+                        "limit": STORAGE_LIST_LIMIT,
+                        "offset": offset,
+                    }))
+                    .send()
+                    .await
+                    .map_err(|e| AppError::UpstreamStorage(e.to_string()))?;
+                let response = self.check(response, "list", &folder).await?;
+
+                let entries: Vec<StorageListEntry> = response.json().await.map_err(|e| {
+                    AppError::UpstreamStorage(format!("Failed to parse storage listing: {}", e))
+                })?;
+
+                let count = entries.len();
+                for entry in entries {
+                    let path = format!("{}/{}", folder, entry.name);
+                    if entry.id.is_some() {
+                        files.push(path);
+                    } else {
+                        folders.push(path);
+                    }
+                }
+
+                if count < STORAGE_LIST_LIMIT {
+                    break;
+                }
+                offset += count;
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn public_url(&self, path: &str) -> String {
+        format!(
+            "{}/storage/v1/object/public/{}/{}",
+            self.url, self.bucket, path
+        )
+    }
+
+    async fn signed_url(&self, path: &str, ttl: Duration) -> Result<String, AppError> {
+        let sign_url = format!(
+            "{}/storage/v1/object/sign/{}/{}",
+            self.url, self.bucket, path
+        );
+
+        let response = self
+            .authorized(self.client.post(&sign_url))
+            .json(&json!({ "expiresIn": ttl.as_secs().max(1) }))
+            .send()
+            .await
+            .map_err(|e| AppError::UpstreamStorage(e.to_string()))?;
+        let response = self.check(response, "sign", path).await?;
+
+        let signed: SignedUrlResponse = response.json().await.map_err(|e| {
+            AppError::UpstreamStorage(format!("Failed to parse signed URL response: {}", e))
+        })?;
+
+        // The returned URL is relative to the storage API root
+        Ok(format!("{}/storage/v1{}", self.url, signed.signed_url))
+    }
+
+    /// Supabase signed upload URLs expire after two hours
+    async fn signed_upload_url(&self, path: &str) -> Result<String, AppError> {
+        let sign_url = format!(
+            "{}/storage/v1/object/upload/sign/{}/{}",
+            self.url, self.bucket, path
+        );
+
+        let response = self
+            .authorized(self.client.post(&sign_url))
+            .send()
+            .await
+            .map_err(|e| AppError::UpstreamStorage(e.to_string()))?;
+        let response = self.check(response, "sign upload URL for", path).await?;
+
+        let signed: SignedUploadUrlResponse = response.json().await.map_err(|e| {
+            AppError::UpstreamStorage(format!("Failed to parse signed URL response: {}", e))
+        })?;
+
+        // The returned URL is relative to the storage API root
+        Ok(format!("{}/storage/v1{}", self.url, signed.url))
+    }
+}
@@ -1,382 +1,316 @@
-use axum::http::StatusCode;
 use postgrest::Postgrest;
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
 use serde::de::DeserializeOwned;
 use std::sync::Arc;
-use tracing::{error, info};
+use tracing::{error, warn};
 
-use crate::{config::VIDEO_BUCKET, models::AppState};
+use crate::{auth::AuthUser, error::AppError, models::AppState};
 
+// SQLSTATE for a foreign key violation, which the show genre trigger also raises
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+
+/// PostgREST access as a particular role. Requests made for a user carry their
+/// access token, so row-level security applies; only the service role bypasses it.
 pub struct SupabaseService {
     pub state: Arc<AppState>,
     pub client: Client,
+    api_key: String,
+    // Sent as the Bearer token, and decides which role PostgREST runs as
+    access_token: String,
 }
 
 impl SupabaseService {
-    pub fn new(state: Arc<AppState>) -> Self {
+    /// Act as the service role, bypassing row-level security. Only for privileged
+    /// internal work such as background processing, never on behalf of a caller.
+    pub fn service(state: Arc<AppState>) -> Self {
+        let key = state.supabase_api_key.clone();
+        Self::with_token(state, key.clone(), key)
+    }
+
+    /// Act as the signed-in user, so their RLS policies apply
+    pub fn for_user(state: Arc<AppState>, user: &AuthUser) -> Self {
+        let (key, token) = (state.supabase_anon_key.clone(), user.access_token.clone());
+        Self::with_token(state, key, token)
+    }
+
+    /// Act as an anonymous visitor with the project's anon key
+    pub fn anonymous(state: Arc<AppState>) -> Self {
+        let key = state.supabase_anon_key.clone();
+        Self::with_token(state, key.clone(), key)
+    }
+
+    /// The signed-in user when there is one, otherwise an anonymous visitor
+    pub fn for_request(state: Arc<AppState>, user: Option<&AuthUser>) -> Self {
+        match user {
+            Some(user) => Self::for_user(state, user),
+            None => Self::anonymous(state),
+        }
+    }
+
+    fn with_token(state: Arc<AppState>, api_key: String, access_token: String) -> Self {
         Self {
             state,
             client: Client::new(),
+            api_key,
+            access_token,
         }
     }
 
+    /// Add the `apikey` and `Authorization` headers for this service's role
+    pub fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+            .header("apikey", &self.api_key)
+            .header("Authorization", format!("Bearer {}", self.access_token))
+    }
+
     pub fn postgrest_client(&self) -> Postgrest {
-        Postgrest::new(&self.state.supabase_url)
-            .insert_header("apikey", &self.state.supabase_api_key)
+        Postgrest::new(format!("{}/rest/v1", self.state.supabase_url))
+            .insert_header("apikey", &self.api_key)
     }
 
-    pub async fn upload_file_with_content_type(
+    pub async fn query_single<T: DeserializeOwned>(
         &self,
-        file_name: &str,
-        content: Vec<u8>,
-        content_type: &str,
-    ) -> Result<(), (StatusCode, String)> {
-        let storage_url = format!(
-            "{}/storage/v1/object/{}/{}",
-            self.state.supabase_url, VIDEO_BUCKET, file_name
-        );
+        table: &str,
+        column: &str,
+        value: &str,
+    ) -> Result<T, AppError> {
+        let url = format!("{}/rest/v1/{}", self.state.supabase_url, table);
 
         let response = self
-            .client
-            .post(&storage_url)
-            .header("apikey", &self.state.supabase_api_key)
-            .header("Content-Type", content_type)
-            .body(content)
+            .authorized(self.client.get(&url))
+            .query(&[
+                (column.to_string(), format!("eq.{}", value)),
+                ("select".to_string(), "*".to_string()),
+            ])
+            .header("Accept", "application/json")
             .send()
             .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            .map_err(|e| {
+                error!("Request error: {}", e);
+                AppError::UpstreamDatabase(e.to_string())
+            })?;
+
+        let status = response.status();
+        let body = response.text().await.map_err(|e| {
+            error!("Error reading response body: {}", e);
+            AppError::UpstreamDatabase(e.to_string())
+        })?;
+
+        if !status.is_success() {
+            return Err(AppError::UpstreamDatabase(format!(
+                "Supabase API error: {} - {}",
+                status, body
+            )));
+        }
 
-        if !response.status().is_success() {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to upload to storage: {}", response.status()),
-            ));
+        // PostgREST returns an array even when a single row matches
+        let mut rows: Vec<T> = serde_json::from_str(&body).map_err(|e| {
+            error!("Deserialization error: {:?}", e);
+            AppError::UpstreamDatabase(format!("Failed to deserialize response: {}", e))
+        })?;
+
+        if rows.is_empty() {
+            return Err(AppError::NotFound(format!(
+                "{} with {} = {} not found",
+                table, column, value
+            )));
         }
 
-        Ok(())
+        Ok(rows.remove(0))
     }
 
-    // pub async fn query_single<T: DeserializeOwned>(
-    //     &self,
-    //     table: &str,
-    //     column: &str,
-    //     value: &str,
-    // ) -> Result<T, (StatusCode, String)> {
-    //     // Let's use the direct HTTP client approach for more control and better debugging
-    //     let url = format!(
-    //         "{}/rest/v1/{}?{}=eq.{}&select=*",
-    //         self.state.supabase_url, table, column, value
-    //     );
-
-    //     info!("Making request to: {}", url);
-
-    //     let response = self
-    //         .client
-    //         .get(&url)
-    //         .header("apikey", &self.state.supabase_api_key)
-    //         .header(
-    //             "Authorization",
-    //             format!("Bearer {}", self.state.supabase_api_key),
-    //         )
-    //         .header("Content-Type", "application/json")
-    //         .header("Accept", "application/json")
-    //         .send()
-    //         .await
-    //         .map_err(|e| {
-    //             error!("Request error: {}", e);
-    //             (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    //         })?;
-
-    //     let status = response.status();
-    //     info!("Response status: {}", status);
-
-    //     let body = response.text().await.map_err(|e| {
-    //         error!("Error reading response body: {}", e);
-    //         (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-    //     })?;
-
-    //     info!("Response body: {}", body);
-
-    //     if !status.is_success() {
-    //         return Err((
-    //             StatusCode::INTERNAL_SERVER_ERROR,
-    //             format!("Supabase API error: {} - {}", status, body),
-    //         ));
-    //     }
-
-    //     // Handle empty responses
-    //     if body.trim().is_empty() || body == "[]" {
-    //         return Err((
-    //             StatusCode::NOT_FOUND,
-    //             format!("{} with {} = {} not found", table, column, value),
-    //         ));
-    //     }
-
-    //     // Parse the response - Supabase returns an array even for single items when using ?single=true
-    //     let parsed: serde_json::Value = serde_json::from_str(&body).map_err(|e| {
-    //         tracing::error!("JSON parse error: {}", e);
-    //         (
-    //             StatusCode::INTERNAL_SERVER_ERROR,
-    //             format!("JSON parse error: {}", e),
-    //         )
-    //     })?;
-
-    //     // If it's an array with one item, extract the item
-    //     let item_value = if parsed.is_array() {
-    //         let array = parsed.as_array().unwrap();
-    //         if array.is_empty() {
-    //             return Err((
-    //                 StatusCode::NOT_FOUND,
-    //                 format!("{} with {} = {} not found", table, column, value),
-    //             ));
-    //         }
-    //         array[0].clone()
-    //     } else {
-    //         parsed
-    //     };
-
-    //     // Deserialize the single object
-    //     serde_json::from_value::<T>(item_value.clone()).map_err(|e| {
-    //         error!("Deserialization error: {:?}", e);
-    //         error!("JSON value: {:?}", item_value.clone());
-    //         (
-    //             StatusCode::INTERNAL_SERVER_ERROR,
-    //             format!(
-    //                 "Failed to deserialize response: {} - Value: {}",
-    //                 e, item_value
-    //             ),
-    //         )
-    //     })
-    // }
-
-    // pub async fn query_all<T>(
-    //     &self,
-    //     table: &str,
-    //     order: Option<&str>,
-    // ) -> Result<Vec<T>, (StatusCode, String)>
-    // where
-    //     T: serde::de::DeserializeOwned,
-    // {
-    //     // Build the URL with optional ordering
-    //     let mut url = format!("{}/rest/v1/{}?select=*", self.state.supabase_url, table);
-
-    //     if let Some(order_by) = order {
-    //         url = format!("{}&order={}", url, order_by);
-    //     }
-
-    //     // Make the request to Supabase
-    //     let response = self
-    //         .client
-    //         .get(&url)
-    //         .header("apikey", &self.state.supabase_api_key)
-    //         .header(
-    //             "Authorization",
-    //             &format!("Bearer {}", self.state.supabase_api_key),
-    //         )
-    //         .send()
-    //         .await
-    //         .map_err(|e| {
-    //             (
-    //                 StatusCode::INTERNAL_SERVER_ERROR,
-    //                 format!("Request error: {}", e),
-    //             )
-    //         })?;
-
-    //     // Check if the response is successful
-    //     if !response.status().is_success() {
-    //         let error_text = response.text().await.unwrap_or_default();
-    //         println!("supabase error");
-    //         return Err((
-    //             StatusCode::INTERNAL_SERVER_ERROR,
-    //             format!("Supabase error: {}", error_text),
-    //         ));
-    //     }
-
-    //     // Get the response body as text for flexible parsing
-    //     let body_text = response.text().await.map_err(|e| {
-    //         (
-    //             StatusCode::INTERNAL_SERVER_ERROR,
-    //             format!("Reading response error: {}", e),
-    //         )
-    //     })?;
-
-    //     // If response is empty, return empty vector
-    //     if body_text.trim().is_empty() {
-    //         return Ok(Vec::new());
-    //     }
-
-    //     // Try to parse as a JSON value first to determine structure
-    //     let json_value: serde_json::Value = serde_json::from_str(&body_text).map_err(|e| {
-    //         (
-    //             StatusCode::INTERNAL_SERVER_ERROR,
-    //             format!("JSON parse error: {}", e),
-    //         )
-    //     })?;
-
-    //     // Handle different response formats
-    //     if json_value.is_array() {
-    //         // Direct array format (most common)
-    //         let items: Vec<T> = serde_json::from_value(json_value).map_err(|e| {
-    //             (
-    //                 StatusCode::INTERNAL_SERVER_ERROR,
-    //                 format!("Array deserialization error: {}", e),
-    //             )
-    //         })?;
-    //         Ok(items)
-    //     } else if json_value.is_object() {
-    //         // Handle object format
-    //         if let Some(data) = json_value.get("data") {
-    //             if data.is_array() {
-    //                 // Object with data array field
-    //                 let items: Vec<T> = serde_json::from_value(data.clone()).map_err(|e| {
-    //                     (
-    //                         StatusCode::INTERNAL_SERVER_ERROR,
-    //                         format!("Object data deserialization error: {}", e),
-    //                     )
-    //                 })?;
-    //                 return Ok(items);
-    //             }
-    //         }
-
-    //         // If object is empty or doesn't have expected format but is valid JSON
-    //         if json_value.as_object().map_or(false, |obj| obj.is_empty()) {
-    //             return Ok(Vec::new());
-    //         }
-
-    //         // Try treating the object itself as a single item
-    //         match serde_json::from_value::<T>(json_value.clone()) {
-    //             Ok(item) => Ok(vec![item]),
-    //             Err(_) => {
-    //                 // Last resort: Try to extract array from another field or just return the error
-    //                 Err((
-    //                     StatusCode::INTERNAL_SERVER_ERROR,
-    //                     format!(
-    //                         "Unexpected response format. Expected array, got object: {}",
-    //                         body_text
-    //                     ),
-    //                 ))
-    //             }
-    //         }
-    //     } else {
-    //         // Neither array nor object (shouldn't happen with valid JSON)
-    //         Err((
-    //             StatusCode::INTERNAL_SERVER_ERROR,
-    //             format!("Unexpected JSON value type: {}", body_text),
-    //         ))
-    //     }
-    // }
-
-    // pub async fn insert<T>(
-    //     &self,
-    //     table: &str,
-    //     data: serde_json::Value,
-    // ) -> Result<(), (StatusCode, String)> {
-    //     // Direct API call approach for better debugging
-    //     let url = format!("{}/rest/v1/{}", self.state.supabase_url, table);
-
-    //     let response = self
-    //         .client
-    //         .post(&url)
-    //         .header("apikey", &self.state.supabase_api_key)
-    //         .header(
-    //             "Authorization",
-    //             format!("Bearer {}", self.state.supabase_api_key),
-    //         )
-    //         .header("Content-Type", "application/json")
-    //         .header("Prefer", "return=minimal")
-    //         .json(&data)
-    //         .send()
-    //         .await
-    //         .map_err(|e| {
-    //             (
-    //                 StatusCode::INTERNAL_SERVER_ERROR,
-    //                 format!("Request error: {}", e),
-    //             )
-    //         })?;
-
-    //     if !response.status().is_success() {
-    //         // let error_text = response.text().await.unwrap_or_default();
-    //         return Err((
-    //             StatusCode::INTERNAL_SERVER_ERROR,
-    //             format!(
-    //                 "Supabase insert error: {} - {}",
-    //                 response.status(),
-    //                 response.text().await.unwrap_or_default()
-    //             ),
-    //         ));
-    //     }
-
-    //     Ok(())
-    // }
-
-    // pub async fn delete(
-    //     &self,
-    //     table: &str,
-    //     column: &str,
-    //     value: &str,
-    // ) -> Result<(), (StatusCode, String)> {
-    //     self.postgrest_client()
-    //         .from(table)
-    //         .delete()
-    //         .eq(column, value)
-    //         .execute()
-    //         .await
-    //         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    //     Ok(())
-    // }
-
-    pub async fn upload_file(
+    /// Fetch rows matching the given PostgREST query parameters, along with the
+    /// total number of matching rows ignoring `range`
+    pub async fn query_all<T>(
         &self,
-        file_name: &str,
-        content: Vec<u8>,
-    ) -> Result<(), (StatusCode, String)> {
-        let storage_url = format!(
-            "{}/storage/v1/object/{}/{}",
-            self.state.supabase_url, VIDEO_BUCKET, file_name
-        );
+        table: &str,
+        params: &[(&str, String)],
+        range: Option<(usize, usize)>,
+    ) -> Result<(Vec<T>, i64), AppError>
+    where
+        T: DeserializeOwned,
+    {
+        let url = format!("{}/rest/v1/{}", self.state.supabase_url, table);
+        let request = self
+            .authorized(self.client.get(&url))
+            .query(&[("select", "*")])
+            .query(params);
+
+        self.fetch_page(request, range).await
+    }
+
+    /// Call a set-returning database function, filtering and paging its rows with
+    /// PostgREST query parameters as `query_all` does for tables
+    pub async fn query_rpc<T>(
+        &self,
+        function: &str,
+        args: serde_json::Value,
+        params: &[(&str, String)],
+        range: Option<(usize, usize)>,
+    ) -> Result<(Vec<T>, i64), AppError>
+    where
+        T: DeserializeOwned,
+    {
+        let url = format!("{}/rest/v1/rpc/{}", self.state.supabase_url, function);
+        let request = self
+            .authorized(self.client.post(&url))
+            .query(params)
+            .json(&args);
+
+        self.fetch_page(request, range).await
+    }
+
+    async fn fetch_page<T>(
+        &self,
+        mut request: RequestBuilder,
+        range: Option<(usize, usize)>,
+    ) -> Result<(Vec<T>, i64), AppError>
+    where
+        T: DeserializeOwned,
+    {
+        request = request.header("Prefer", "count=exact");
+        if let Some((from, to)) = range {
+            request = request
+                .header("Range-Unit", "items")
+                .header("Range", format!("{}-{}", from, to));
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| AppError::UpstreamDatabase(format!("Request error: {}", e)))?;
+
+        // Content-Range looks like `0-9/42`, or `*/0` when nothing matched
+        let total = response
+            .headers()
+            .get("content-range")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit('/').next())
+            .and_then(|v| v.parse::<i64>().ok());
+
+        let status = response.status();
+        // A page starting past the last row is empty rather than an error
+        if status == StatusCode::RANGE_NOT_SATISFIABLE && range.is_some() {
+            return Ok((Vec::new(), total.unwrap_or(0)));
+        }
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            error!("Supabase query error: {} - {}", status, error_text);
+            return Err(AppError::UpstreamDatabase(format!(
+                "Supabase error: {}",
+                error_text
+            )));
+        }
+
+        let items: Vec<T> = response.json().await.map_err(|e| {
+            AppError::UpstreamDatabase(format!("Array deserialization error: {}", e))
+        })?;
+
+        let total = total.unwrap_or(items.len() as i64);
+        Ok((items, total))
+    }
+
+    /// Insert a row and return it as stored, including generated columns
+    pub async fn insert<T>(&self, table: &str, data: serde_json::Value) -> Result<T, AppError>
+    where
+        T: DeserializeOwned,
+    {
+        let url = format!("{}/rest/v1/{}", self.state.supabase_url, table);
 
         let response = self
-            .client
-            .post(&storage_url)
-            .header("apikey", &self.state.supabase_api_key)
-            .header("Content-Type", "video/mp4")
-            .body(content)
+            .authorized(self.client.post(&url))
+            .header("Content-Type", "application/json")
+            .header("Prefer", "return=representation")
+            .json(&data)
             .send()
             .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            .map_err(|e| AppError::UpstreamDatabase(format!("Request error: {}", e)))?;
 
         if !response.status().is_success() {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to upload to storage: {}", response.status()),
-            ));
+            return Err(write_error(table, "insert", response).await);
         }
 
-        Ok(())
+        // PostgREST returns the inserted rows as an array
+        let mut rows: Vec<T> = response.json().await.map_err(|e| {
+            error!("Failed to parse insert response: {}", e);
+            AppError::UpstreamDatabase(format!("Failed to parse insert response: {}", e))
+        })?;
+
+        if rows.is_empty() {
+            return Err(AppError::UpstreamDatabase(format!(
+                "No {} row returned after insert",
+                table
+            )));
+        }
+
+        Ok(rows.remove(0))
     }
 
-    pub async fn delete_file(&self, file_name: &str) -> Result<(), (StatusCode, String)> {
-        let storage_url = format!(
-            "{}/storage/v1/object/{}/{}",
-            self.state.supabase_url, VIDEO_BUCKET, file_name
-        );
+    /// Delete the rows matching `column = value`. Fails with 404 when there are
+    /// none, and with 403 when RLS lets the caller see them but not delete them.
+    pub async fn delete(&self, table: &str, column: &str, value: &str) -> Result<(), AppError> {
+        let url = format!("{}/rest/v1/{}", self.state.supabase_url, table);
 
-        self.client
-            .delete(&storage_url)
-            .header("apikey", &self.state.supabase_api_key)
+        let response = self
+            .authorized(self.client.delete(&url))
+            .query(&[(column, format!("eq.{}", value))])
+            .header("Prefer", "return=representation")
             .send()
             .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            .map_err(|e| AppError::UpstreamDatabase(e.to_string()))?;
 
-        Ok(())
+        if !response.status().is_success() {
+            return Err(write_error(table, "delete", response).await);
+        }
+
+        let deleted: Vec<serde_json::Value> = response.json().await.map_err(|e| {
+            AppError::UpstreamDatabase(format!("Failed to parse delete response: {}", e))
+        })?;
+        if !deleted.is_empty() {
+            return Ok(());
+        }
+
+        // RLS filters out rows it won't let the caller delete instead of failing
+        match self
+            .query_single::<serde_json::Value>(table, column, value)
+            .await
+        {
+            Ok(_) => Err(AppError::Forbidden(format!(
+                "Not allowed to delete {} with {} = {}",
+                table, column, value
+            ))),
+            Err(err) => Err(err),
+        }
     }
 
-    pub fn get_public_url(&self, file_name: &str) -> String {
-        format!(
-            "{}/storage/v1/object/public/{}/{}",
-            self.state.supabase_url, VIDEO_BUCKET, file_name
-        )
+    /// Update the rows matching every filter and return them as stored
+    pub async fn update_returning<T>(
+        &self,
+        table: &str,
+        filters: &[(&str, String)],
+        data: serde_json::Value,
+    ) -> Result<Vec<T>, AppError>
+    where
+        T: DeserializeOwned,
+    {
+        let url = format!("{}/rest/v1/{}", self.state.supabase_url, table);
+
+        let response = self
+            .authorized(self.client.patch(&url))
+            .query(filters)
+            .header("Prefer", "return=representation")
+            .json(&data)
+            .send()
+            .await
+            .map_err(|e| AppError::UpstreamDatabase(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(write_error(table, "update", response).await);
+        }
+
+        response.json().await.map_err(|e| {
+            AppError::UpstreamDatabase(format!("Failed to parse update response: {}", e))
+        })
     }
 
     pub async fn update(
@@ -385,29 +319,101 @@ impl SupabaseService {
         column: &str,
         value: &str,
         data: serde_json::Value,
-    ) -> Result<(), (StatusCode, String)> {
-        self.postgrest_client()
-            .from(table)
-            .update(data.to_string())
-            .eq(column, value)
+    ) -> Result<(), AppError> {
+        let url = format!("{}/rest/v1/{}", self.state.supabase_url, table);
+
+        let response = self
+            .authorized(self.client.patch(&url))
+            .query(&[(column, format!("eq.{}", value))])
+            .json(&data)
+            .send()
+            .await
+            .map_err(|e| AppError::UpstreamDatabase(e.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(write_error(table, "update", response).await);
+        }
+
+        Ok(())
+    }
+
+    /// Call a database function for its side effects
+    pub async fn call_rpc(
+        &self,
+        function: &str,
+        params: serde_json::Value,
+    ) -> Result<(), AppError> {
+        let response = self
+            .postgrest_client()
+            .rpc(function, params.to_string())
+            .auth(&self.access_token)
             .execute()
             .await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            .map_err(|e| AppError::UpstreamDatabase(e.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(AppError::UpstreamDatabase(format!(
+                "Supabase rpc {} error: {} - {}",
+                function,
+                status,
+                response.text().await.unwrap_or_default()
+            )));
+        }
 
         Ok(())
     }
+}
+
+/// Turn a failed PostgREST write into an error. Rows a constraint rejects are the
+/// client's fault; the database's own wording is only logged.
+async fn write_error(table: &str, operation: &str, response: Response) -> AppError {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    let error: serde_json::Value = serde_json::from_str(&body).unwrap_or_default();
+    let code = error["code"].as_str().unwrap_or_default();
+
+    // PostgREST answers 409 for unique and foreign key violations, and 400 with a
+    // `22` or `23` SQLSTATE when the data breaks a check or not-null constraint
+    let rejection = if code == FOREIGN_KEY_VIOLATION && operation == "delete" {
+        AppError::Conflict(format!(
+            "The {} row is still referenced by other rows",
+            table
+        ))
+    } else if code == FOREIGN_KEY_VIOLATION {
+        AppError::Conflict(format!(
+            "The {} row refers to a row that doesn't exist",
+            table
+        ))
+    } else if status == StatusCode::CONFLICT {
+        AppError::Conflict(format!("The {} row conflicts with an existing one", table))
+    } else if status == StatusCode::BAD_REQUEST
+        && (code.starts_with("22") || code.starts_with("23"))
+    {
+        AppError::Validation(format!(
+            "The {} row was rejected by a database constraint",
+            table
+        ))
+    } else {
+        return AppError::UpstreamDatabase(format!(
+            "Supabase {} error: {} - {}",
+            operation, status, body
+        ));
+    };
+
+    warn!("Supabase {} on {} rejected: {}", operation, table, body);
+    rejection
+}
+
+/// Inclusive row range for a page of `limit` rows starting at `offset`
+pub fn page_range(offset: usize, limit: usize) -> (usize, usize) {
+    (offset, offset.saturating_add(limit.saturating_sub(1)))
+}
 
-    // pub async fn call_rpc(
-    //     &self,
-    //     function: &str,
-    //     params: serde_json::Value,
-    // ) -> Result<(), (StatusCode, String)> {
-    //     self.postgrest_client()
-    //         .rpc(function, &params.to_string())
-    //         .execute()
-    //         .await
-    //         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    //     Ok(())
-    // }
+/// Where the page after one of `returned` rows starts, or `None` on the last page
+pub fn next_offset(offset: usize, returned: usize, total: i64) -> Option<usize> {
+    let next = offset.saturating_add(returned);
+    i64::try_from(next)
+        .is_ok_and(|next| next < total)
+        .then_some(next)
 }
@@ -0,0 +1,396 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{body::Body, http::StatusCode};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+pub const TUS_VERSION: &str = "1.0.0";
+pub const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+
+// How often abandoned uploads are looked for
+const SWEEP_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// A partial upload as recorded next to its data file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TusUpload {
+    pub id: String,
+    pub length: u64,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    // Set once the completed upload has been handed to the processing pipeline
+    #[serde(default)]
+    pub video_id: Option<String>,
+    // The user who created the upload
+    #[serde(default)]
+    pub user_id: Option<String>,
+}
+
+/// Keeps partial tus uploads on local disk as `{id}.bin` plus a `{id}.json` info file.
+/// The current offset is always the size of the data file, and an upload expires
+/// once nothing has been written to it for `ttl`.
+pub struct TusStore {
+    dir: PathBuf,
+    pub ttl: Duration,
+    // Uploads currently receiving a PATCH, so concurrent writers can be rejected
+    active: Mutex<HashSet<String>>,
+}
+
+/// Marks an upload as being written to until dropped
+pub struct TusLock<'a> {
+    store: &'a TusStore,
+    id: String,
+}
+
+impl Drop for TusLock<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut active) = self.store.active.lock() {
+            active.remove(&self.id);
+        }
+    }
+}
+
+impl TusStore {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> std::io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            ttl,
+            active: Mutex::new(HashSet::new()),
+        })
+    }
+
+    pub fn data_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.bin", id))
+    }
+
+    fn info_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    pub async fn create(
+        &self,
+        length: u64,
+        metadata: HashMap<String, String>,
+        user_id: &str,
+    ) -> Result<TusUpload, AppError> {
+        let upload = TusUpload {
+            id: Uuid::new_v4().to_string(),
+            length,
+            metadata,
+            video_id: None,
+            user_id: Some(user_id.to_string()),
+        };
+
+        self.save(&upload).await?;
+        fs::File::create(self.data_path(&upload.id))
+            .await
+            .map_err(io_error)?;
+
+        info!("Created tus upload {} ({} bytes)", upload.id, length);
+        Ok(upload)
+    }
+
+    pub async fn save(&self, upload: &TusUpload) -> Result<(), AppError> {
+        let info = serde_json::to_vec(upload).map_err(|e| AppError::Internal(e.to_string()))?;
+        fs::write(self.info_path(&upload.id), info)
+            .await
+            .map_err(io_error)
+    }
+
+    /// Load an upload together with its current offset. An expired upload answers
+    /// 410 until it is swept away.
+    pub async fn get(&self, id: &str) -> Result<(TusUpload, u64), AppError> {
+        let upload = self.read_info(id).await?;
+        if self
+            .expires_at(&upload)
+            .await
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+        {
+            return Err(AppError::Rejected(
+                StatusCode::GONE,
+                format!("Upload {} has expired", id),
+            ));
+        }
+
+        let offset = fs::metadata(self.data_path(id))
+            .await
+            .map_err(io_error)?
+            .len();
+
+        Ok((upload, offset))
+    }
+
+    async fn read_info(&self, id: &str) -> Result<TusUpload, AppError> {
+        let info = match fs::read(self.info_path(id)).await {
+            Ok(info) => info,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(AppError::NotFound(format!("Upload {} not found", id)));
+            }
+            Err(e) => return Err(io_error(e)),
+        };
+        serde_json::from_slice(&info).map_err(|e| AppError::Internal(e.to_string()))
+    }
+
+    /// When the upload expires, `ttl` after it was last written to. Uploads handed
+    /// to processing are removed by their job instead and never expire.
+    pub async fn expires_at(&self, upload: &TusUpload) -> Option<DateTime<Utc>> {
+        if upload.video_id.is_some() {
+            return None;
+        }
+
+        let modified = fs::metadata(self.data_path(&upload.id))
+            .await
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+        Some(DateTime::<Utc>::from(modified) + self.ttl)
+    }
+
+    /// Remove every expired upload, returning how many there were. Uploads being
+    /// written to are skipped, since a write postpones their expiry.
+    pub async fn remove_expired(&self) -> Result<usize, AppError> {
+        let mut entries = fs::read_dir(&self.dir).await.map_err(io_error)?;
+        let mut removed = 0;
+
+        while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let Ok(_lock) = self.lock(id) else {
+                continue;
+            };
+            let Ok(upload) = self.read_info(id).await else {
+                continue;
+            };
+
+            if self
+                .expires_at(&upload)
+                .await
+                .is_some_and(|expires_at| expires_at <= Utc::now())
+            {
+                self.remove(id).await?;
+                info!("Removed expired tus upload {}", id);
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    pub fn lock(&self, id: &str) -> Result<TusLock<'_>, AppError> {
+        let mut active = self
+            .active
+            .lock()
+            .map_err(|_| AppError::Internal("Upload lock poisoned".to_string()))?;
+
+        if !active.insert(id.to_string()) {
+            return Err(AppError::Rejected(
+                StatusCode::LOCKED,
+                format!("Upload {} is already being written to", id),
+            ));
+        }
+
+        Ok(TusLock {
+            store: self,
+            id: id.to_string(),
+        })
+    }
+
+    /// Append a request body at `offset`, returning the new offset. Bytes received
+    /// before a dropped connection are kept so the client can resume from them.
+    pub async fn append(
+        &self,
+        upload: &TusUpload,
+        offset: u64,
+        body: Body,
+    ) -> Result<u64, AppError> {
+        let path = self.data_path(&upload.id);
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(io_error)?;
+
+        let mut written: u64 = 0;
+        let mut stream = body.into_data_stream();
+        let mut result = Ok(());
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    error!("Upload {} interrupted: {}", upload.id, e);
+                    result = Err(AppError::Validation(format!("Failed to read body: {}", e)));
+                    break;
+                }
+            };
+
+            if offset + written + chunk.len() as u64 > upload.length {
+                // Drop everything from this request rather than keep a partial overflow
+                file.set_len(offset).await.map_err(io_error)?;
+                return Err(AppError::Rejected(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!(
+                        "Upload {} would exceed its length of {} bytes",
+                        upload.id, upload.length
+                    ),
+                ));
+            }
+
+            file.write_all(&chunk).await.map_err(io_error)?;
+            written += chunk.len() as u64;
+        }
+
+        file.flush().await.map_err(io_error)?;
+        result.map(|_| offset + written)
+    }
+
+    pub async fn remove(&self, id: &str) -> Result<(), AppError> {
+        for path in [self.data_path(id), self.info_path(id)] {
+            remove_if_exists(&path).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Periodically remove uploads that were abandoned before they completed
+pub fn start_sweeper(store: Arc<TusStore>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = store.remove_expired().await {
+                error!("Failed to remove expired tus uploads: {}", e);
+            }
+        }
+    });
+}
+
+async fn remove_if_exists(path: &Path) -> Result<(), AppError> {
+    match fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(io_error(e)),
+    }
+}
+
+fn io_error(e: std::io::Error) -> AppError {
+    error!("Upload storage error: {}", e);
+    AppError::Internal("Upload storage error".to_string())
+}
+
+/// Parse an `Upload-Metadata` header: comma separated `key base64value` pairs
+pub fn parse_metadata(header: &str) -> Result<HashMap<String, String>, AppError> {
+    let mut metadata = HashMap::new();
+
+    for pair in header.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once(' ').unwrap_or((pair, ""));
+        let decoded = STANDARD
+            .decode(value.trim())
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(|| {
+                AppError::Validation(format!("Invalid Upload-Metadata value for key {}", key))
+            })?;
+        metadata.insert(key.to_string(), decoded);
+    }
+
+    Ok(metadata)
+}
+
+pub fn encode_metadata(metadata: &HashMap<String, String>) -> String {
+    metadata
+        .iter()
+        .map(|(key, value)| format!("{} {}", key, STANDARD.encode(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    async fn store(ttl: Duration) -> (TempDir, TusStore) {
+        let dir = TempDir::new().unwrap();
+        let store = TusStore::new(dir.path(), ttl).unwrap();
+        (dir, store)
+    }
+
+    #[tokio::test]
+    async fn fresh_upload_expires_after_ttl() {
+        let (_dir, store) = store(Duration::from_secs(3600)).await;
+        let upload = store.create(10, HashMap::new(), "alice").await.unwrap();
+
+        let expires_at = store.expires_at(&upload).await.unwrap();
+        assert!(expires_at > Utc::now() + Duration::from_secs(3500));
+        assert_eq!(store.get(&upload.id).await.unwrap().1, 0);
+        assert_eq!(store.remove_expired().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn expired_upload_is_gone_and_swept() {
+        let (_dir, store) = store(Duration::ZERO).await;
+        let upload = store.create(10, HashMap::new(), "alice").await.unwrap();
+
+        let err = store.get(&upload.id).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::GONE);
+
+        assert_eq!(store.remove_expired().await.unwrap(), 1);
+        assert!(!store.data_path(&upload.id).exists());
+        let err = store.get(&upload.id).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn claimed_and_locked_uploads_are_kept() {
+        let (_dir, store) = store(Duration::ZERO).await;
+
+        let mut claimed = store.create(10, HashMap::new(), "alice").await.unwrap();
+        claimed.video_id = Some("video".to_string());
+        store.save(&claimed).await.unwrap();
+        assert_eq!(store.expires_at(&claimed).await, None);
+
+        let writing = store.create(10, HashMap::new(), "alice").await.unwrap();
+        let lock = store.lock(&writing.id).unwrap();
+        assert_eq!(store.remove_expired().await.unwrap(), 0);
+
+        drop(lock);
+        assert_eq!(store.remove_expired().await.unwrap(), 1);
+        assert!(store.get(&claimed.id).await.is_ok());
+    }
+
+    #[test]
+    fn metadata_round_trips() {
+        let metadata = HashMap::from([
+            ("filename".to_string(), "clip.mp4".to_string()),
+            ("title".to_string(), "Ünïcode, commas".to_string()),
+        ]);
+
+        assert_eq!(
+            parse_metadata(&encode_metadata(&metadata)).unwrap(),
+            metadata
+        );
+        assert!(parse_metadata("filename not-base64!").is_err());
+        assert_eq!(parse_metadata("flag").unwrap()["flag"], "");
+    }
+}
@@ -1,176 +1,566 @@
 use anyhow::Result;
 use axum::http::StatusCode;
-use std::io::Write;
-use std::path::Path;
-use std::process::{Command, Stdio};
-use tempfile::NamedTempFile;
-use tokio::fs;
-use tracing::{error, info};
-use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::{
+    fs,
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    encoding::{Container, EncodingProfile, ManifestFormat, RateControl, Rendition, VideoCodec},
+    error::AppError,
+};
+
+// How much of ffmpeg's stderr to keep as the error detail when it fails
+const STDERR_TAIL_LINES: usize = 20;
+
+pub const THUMBNAIL_FILE: &str = "thumbnail.jpg";
+// CMAF segments shared by every manifest, alongside the manifests themselves
+pub const STREAM_DIR: &str = "stream";
+pub const HLS_MASTER_PLAYLIST: &str = "master.m3u8";
+pub const DASH_MANIFEST: &str = "manifest.mpd";
+
+/// What ffprobe reports about an input file. Field names match the columns the
+/// probe is saved to on the video row.
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaInfo {
+    // ffprobe format name, e.g. `mov,mp4,m4a,3gp,3g2,mj2`
+    pub container: String,
+    pub duration: Option<f64>,
+    pub video_codec: String,
+    pub audio_codec: Option<String>,
+    // Coded dimensions, before rotation is applied
+    pub width: u32,
+    pub height: u32,
+    // Display rotation in degrees, clockwise
+    pub rotation: i32,
+    pub frame_rate: Option<f64>,
+    pub audio_channels: Option<u32>,
+    // Overall bitrate in bits per second
+    pub bit_rate: Option<u64>,
+}
+
+impl MediaInfo {
+    pub fn has_audio(&self) -> bool {
+        self.audio_codec.is_some()
+    }
+
+    /// Height of the picture as played back. ffmpeg applies the rotation while
+    /// decoding, so this is what the ladder is scaled from.
+    pub fn display_height(&self) -> u32 {
+        if self.rotation.rem_euclid(180) == 90 {
+            self.width
+        } else {
+            self.height
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct FfprobeOutput {
+    #[serde(default)]
+    streams: Vec<FfprobeStream>,
+    format: Option<FfprobeFormat>,
+}
+
+#[derive(Deserialize)]
+struct FfprobeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    avg_frame_rate: Option<String>,
+    r_frame_rate: Option<String>,
+    channels: Option<u32>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+    #[serde(default)]
+    side_data_list: Vec<FfprobeSideData>,
+    #[serde(default)]
+    disposition: HashMap<String, i32>,
+}
+
+#[derive(Deserialize)]
+struct FfprobeSideData {
+    rotation: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct FfprobeFormat {
+    format_name: Option<String>,
+    duration: Option<String>,
+    bit_rate: Option<String>,
+}
+
+/// ffprobe reports frame rates as fractions such as `30000/1001`
+fn parse_frame_rate(rate: &str) -> Option<f64> {
+    let (num, den) = rate.split_once('/').unwrap_or((rate, "1"));
+    let (num, den) = (num.parse::<f64>().ok()?, den.parse::<f64>().ok()?);
+    (num > 0.0 && den > 0.0).then(|| num / den)
+}
+
+fn not_a_video(detail: impl Into<String>) -> AppError {
+    AppError::Rejected(StatusCode::UNPROCESSABLE_ENTITY, detail.into())
+}
+
+/// Files written by `process_video` into its output directory
+pub struct ProcessedVideo {
+    pub video_file: PathBuf,
+    pub thumbnail_file: PathBuf,
+    // Holds the CMAF init and media segments plus the manifests below
+    pub stream_dir: PathBuf,
+    pub hls_master: Option<PathBuf>,
+    pub dash_manifest: Option<PathBuf>,
+    pub renditions: Vec<Rendition>,
+}
+
+/// Where to take the thumbnail from: 1 second in, or halfway through clips
+/// shorter than 2 seconds so the seek never lands past the last frame. Falls back
+/// to the first frame when the duration couldn't be probed.
+fn thumbnail_seek(duration: Option<f64>) -> f64 {
+    duration.map_or(0.0, |d| (d / 2.0).clamp(0.0, 1.0))
+}
 
 pub struct VideoProcessor;
 
 impl VideoProcessor {
-    /// Process a video using FFmpeg and return the path to the processed file
+    /// Transcode a video into `output_dir` with an encoding profile: a progressive
+    /// download, a thumbnail and a ladder of CMAF segments capped at the source
+    /// resolution, described by the manifests the profile asks for. `on_progress`
+    /// is called with the percent of the input encoded so far.
     pub async fn process_video(
-        video_data: &[u8],
-        filename: &str,
-    ) -> Result<(String, String), (StatusCode, String)> {
-        // Generate a unique ID for this video
-        let video_id = Uuid::new_v4().to_string();
-
-        // Create temporary file for the input
-        let mut input_file = NamedTempFile::new().map_err(|e| {
-            error!("Failed to create temp file: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to create temporary file".to_string(),
-            )
-        })?;
+        input_path: &Path,
+        media: &MediaInfo,
+        output_dir: &Path,
+        profile: &EncodingProfile,
+        on_progress: impl Fn(f32),
+    ) -> Result<ProcessedVideo, AppError> {
+        // Get the path of the input file
+        let input = input_path
+            .to_str()
+            .ok_or_else(|| AppError::Internal("Invalid input file path".to_string()))?;
 
-        // Write video data to the temp file
-        input_file.write_all(video_data).map_err(|e| {
-            error!("Failed to write to temp file: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to write to temporary file".to_string(),
-            )
-        })?;
+        if media.duration.is_none() {
+            warn!(
+                "Could not determine duration of {}, progress will not be reported",
+                input
+            );
+        }
 
-        // Get the path of the input file
-        let input_path = input_file.path().to_str().ok_or_else(|| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Invalid temporary file path".to_string(),
-            )
-        })?;
+        // Cap the output at the profile's resolution limit as well as the source's
+        let output_height = profile.max_height.map_or(media.display_height(), |cap| {
+            cap.min(media.display_height())
+        });
+        let renditions = Rendition::select(&profile.ladder, output_height);
+        info!(
+            "Encoding {}x{} {} source as {} with profile {}",
+            media.width,
+            media.height,
+            media.video_codec,
+            renditions
+                .iter()
+                .map(|r| r.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            profile.name
+        );
 
-        // Create temp file for the output
-        let output_file = NamedTempFile::new().map_err(|e| {
-            error!("Failed to create output temp file: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to create output temporary file".to_string(),
-            )
+        let video_file = output_dir.join(profile.video_file_name());
+        let thumbnail_file = output_dir.join(THUMBNAIL_FILE);
+        let stream_dir = output_dir.join(STREAM_DIR);
+        fs::create_dir_all(&stream_dir).await.map_err(|e| {
+            error!("Failed to create stream directory: {}", e);
+            AppError::Internal("Failed to create output directory".to_string())
         })?;
 
-        let output_path = output_file.path().to_str().ok_or_else(|| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Invalid output file path".to_string(),
-            )
-        })?;
+        let transcode_args = Self::transcode_args(
+            input,
+            &video_file,
+            &stream_dir,
+            &renditions,
+            output_height,
+            media.has_audio(),
+            profile,
+        );
+        Self::run_ffmpeg(&transcode_args, media.duration, &on_progress)
+            .await
+            .map_err(|detail| {
+                AppError::Transcode(format!("FFmpeg processing failed: {}", detail))
+            })?;
 
-        // Create temp file for the thumbnail
-        let thumbnail_file = NamedTempFile::new().map_err(|e| {
-            error!("Failed to create thumbnail temp file: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to create thumbnail temporary file".to_string(),
-            )
-        })?;
+        // The DASH muxer always writes an MPD, so drop it when only HLS was asked for
+        let dash_manifest = stream_dir.join(DASH_MANIFEST);
+        let dash_manifest = if profile.manifests.contains(&ManifestFormat::Dash) {
+            Some(dash_manifest)
+        } else {
+            fs::remove_file(&dash_manifest).await.map_err(|e| {
+                error!("Failed to remove unused DASH manifest: {}", e);
+                AppError::Internal("Failed to clean up processed output".to_string())
+            })?;
+            None
+        };
+        let hls_master = profile
+            .manifests
+            .contains(&ManifestFormat::Hls)
+            .then(|| stream_dir.join(HLS_MASTER_PLAYLIST));
 
-        let thumbnail_path = thumbnail_file.path().to_str().ok_or_else(|| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Invalid thumbnail file path".to_string(),
-            )
-        })?;
+        // Generate thumbnail from a frame near the start
+        let thumbnail_args = [
+            "-ss".to_string(),
+            format!("{:.3}", thumbnail_seek(media.duration)),
+            "-i".to_string(),
+            input.to_string(),
+            "-vframes".to_string(),
+            "1".to_string(),
+            "-y".to_string(),
+            path_arg(&thumbnail_file),
+        ];
+        Self::run_ffmpeg(&thumbnail_args, None, &|_| {})
+            .await
+            .map_err(|detail| {
+                AppError::Transcode(format!("Thumbnail generation failed: {}", detail))
+            })?;
 
-        // Process the video (compress and convert to MP4)
-        let process_result = Command::new("ffmpeg")
-            .arg("-i")
+        info!("Video processed successfully into {:?}", output_dir);
+
+        Ok(ProcessedVideo {
+            video_file,
+            thumbnail_file,
+            stream_dir,
+            hls_master,
+            dash_manifest,
+            renditions,
+        })
+    }
+
+    /// One ffmpeg run producing both the progressive download and every rendition. The
+    /// renditions are packaged once as fragmented MP4 by the DASH muxer, which can
+    /// also write HLS playlists that point at the same segments.
+    fn transcode_args(
+        input: &str,
+        video_file: &Path,
+        stream_dir: &Path,
+        renditions: &[Rendition],
+        output_height: u32,
+        has_audio: bool,
+        profile: &EncodingProfile,
+    ) -> Vec<String> {
+        let mut args: Vec<String> = vec!["-i".into(), input.into()];
+        let encoder = profile.codec.encoder();
+
+        // Split the decoded video once: one copy for the progressive download and a
+        // scaled copy for every rendition
+        let splits: String = (0..renditions.len()).map(|i| format!("[s{}]", i)).collect();
+        let mut filter = format!("[0:v]split={}[sp]{}", renditions.len() + 1, splits);
+        // Downscale the progressive download only when it's over the profile's cap
+        filter.push_str(&format!(
+            ";[sp]scale=-2:'min({},ih)'[vp]",
+            (output_height / 2) * 2
+        ));
+        for (i, rendition) in renditions.iter().enumerate() {
+            filter.push_str(&format!(";[s{}]scale=-2:{}[v{}]", i, rendition.height, i));
+        }
+        args.extend(["-filter_complex".into(), filter]);
+
+        // Progressive download
+        args.extend(["-map".into(), "[vp]".into(), "-map".into(), "0:a:0?".into()]);
+        args.extend(["-c:v".into(), encoder.into()]);
+        match profile.rate_control {
+            // Compression quality (lower = better quality, higher = smaller file)
+            RateControl::Crf(crf) => {
+                args.extend(["-crf".into(), crf.to_string()]);
+                // libvpx only honours CRF when the bitrate is left unconstrained
+                if profile.codec == VideoCodec::Vp9 {
+                    args.extend(["-b:v".into(), "0".into()]);
+                }
+            }
+            RateControl::TargetBitrate { kbps } => args.extend([
+                "-b:v".into(),
+                format!("{}k", kbps),
+                "-maxrate".into(),
+                format!("{}k", kbps * 107 / 100),
+                "-bufsize".into(),
+                format!("{}k", kbps * 3 / 2),
+            ]),
+        }
+        // Encoding speed/compression trade-off
+        args.extend(profile.codec.preset_args("", &profile.preset));
+        args.extend([
+            "-c:a".into(),
+            profile.audio_codec.encoder().into(),
+            "-b:a".into(),
+            format!("{}k", profile.audio_bitrate_kbps),
+        ]);
+        if profile.container == Container::Mp4 {
+            args.extend(["-movflags".into(), "+faststart".into()]);
+        }
+        // Overwrite output file if it exists
+        args.extend(["-y".into(), path_arg(video_file)]);
+
+        // Renditions, with keyframes forced on segment boundaries so players switch cleanly
+        for (i, rendition) in renditions.iter().enumerate() {
+            args.extend([
+                "-map".into(),
+                format!("[v{}]", i),
+                format!("-c:v:{}", i),
+                encoder.into(),
+                format!("-b:v:{}", i),
+                format!("{}k", rendition.video_bitrate_kbps),
+                format!("-maxrate:v:{}", i),
+                format!("{}k", rendition.video_bitrate_kbps * 107 / 100),
+                format!("-bufsize:v:{}", i),
+                format!("{}k", rendition.video_bitrate_kbps * 3 / 2),
+            ]);
+
+            if has_audio {
+                args.extend([
+                    "-map".into(),
+                    "0:a:0".into(),
+                    format!("-c:a:{}", i),
+                    profile.audio_codec.encoder().into(),
+                    format!("-b:a:{}", i),
+                    format!("{}k", rendition.audio_bitrate_kbps),
+                ]);
+            }
+        }
+
+        let adaptation_sets = if has_audio {
+            "id=0,streams=v id=1,streams=a"
+        } else {
+            "id=0,streams=v"
+        };
+
+        args.extend(profile.codec.preset_args(":v", &profile.preset));
+        args.extend([
+            "-force_key_frames".into(),
+            format!("expr:gte(t,n_forced*{})", profile.segment_seconds),
+            "-sc_threshold".into(),
+            "0".into(),
+            "-f".into(),
+            "dash".into(),
+            "-seg_duration".into(),
+            profile.segment_seconds.to_string(),
+            "-use_template".into(),
+            "1".into(),
+            "-use_timeline".into(),
+            "1".into(),
+            "-init_seg_name".into(),
+            "init-$RepresentationID$.m4s".into(),
+            "-media_seg_name".into(),
+            "chunk-$RepresentationID$-$Number%05d$.m4s".into(),
+            "-adaptation_sets".into(),
+            adaptation_sets.into(),
+        ]);
+
+        if profile.manifests.contains(&ManifestFormat::Hls) {
+            args.extend([
+                "-hls_playlist".into(),
+                "1".into(),
+                "-hls_master_name".into(),
+                HLS_MASTER_PLAYLIST.into(),
+            ]);
+        }
+
+        args.extend(["-y".into(), path_arg(&stream_dir.join(DASH_MANIFEST))]);
+        args
+    }
+
+    /// Probe an upload and check it is a video the pipeline accepts, rejecting it
+    /// with 422 when it isn't video or runs longer than `max_duration_secs`
+    pub async fn inspect(input_path: &Path, max_duration_secs: u64) -> Result<MediaInfo, AppError> {
+        let media = Self::probe(input_path).await?;
+
+        if let Some(duration) = media.duration
+            && duration > max_duration_secs as f64
+        {
+            return Err(not_a_video(format!(
+                "Video is {:.0} seconds long, the maximum is {} seconds",
+                duration, max_duration_secs
+            )));
+        }
+
+        Ok(media)
+    }
+
+    /// Inspect the input with ffprobe
+    pub async fn probe(input_path: &Path) -> Result<MediaInfo, AppError> {
+        let output = Command::new("ffprobe")
+            .args([
+                "-v",
+                "error",
+                "-show_format",
+                "-show_streams",
+                "-of",
+                "json",
+            ])
             .arg(input_path)
-            .arg("-c:v")
-            .arg("libx264")
-            .arg("-crf")
-            .arg("23") // Compression quality (lower = better quality, higher = smaller file)
-            .arg("-preset")
-            .arg("medium") // Encoding speed/compression trade-off
-            .arg("-c:a")
-            .arg("aac")
-            .arg("-b:a")
-            .arg("128k")
-            .arg("-y") // Overwrite output file if it exists
-            .arg(output_path)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
+            .stdin(Stdio::null())
+            .output()
+            .await
             .map_err(|e| {
-                error!("FFmpeg process error: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed to process video: {}", e),
-                )
+                error!("ffprobe process error: {}", e);
+                AppError::Transcode(format!("Failed to probe video: {}", e))
             })?;
 
-        if !process_result.success() {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "FFmpeg processing failed".to_string(),
-            ));
+        // ffprobe fails on anything it can't demux
+        if !output.status.success() {
+            let detail = String::from_utf8_lossy(&output.stderr);
+            info!("ffprobe rejected {:?}: {}", input_path, detail.trim());
+            return Err(not_a_video("File is not a recognized video format"));
         }
 
-        // Generate thumbnail from the first frame
-        let thumbnail_result = Command::new("ffmpeg")
-            .arg("-i")
-            .arg(input_path)
-            .arg("-ss")
-            .arg("00:00:01") // 1 second into the video
-            .arg("-vframes")
-            .arg("1")
-            .arg("-y")
-            .arg(thumbnail_path)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
+        let probed: FfprobeOutput = serde_json::from_slice(&output.stdout).map_err(|e| {
+            error!("Failed to parse ffprobe output: {}", e);
+            AppError::Transcode("Failed to probe video".to_string())
+        })?;
+
+        let format = probed
+            .format
+            .ok_or_else(|| not_a_video("File is not a recognized video format"))?;
+        let container = format.format_name.unwrap_or_default();
+        // Still images are demuxed as single-frame video streams
+        if container.starts_with("image2") || container.ends_with("_pipe") {
+            return Err(not_a_video("File is an image, not a video"));
+        }
+
+        // Cover art in audio files shows up as an attached picture video stream
+        let video = probed
+            .streams
+            .iter()
+            .find(|s| {
+                s.codec_type.as_deref() == Some("video")
+                    && s.disposition.get("attached_pic").copied().unwrap_or(0) == 0
+            })
+            .ok_or_else(|| not_a_video("File has no video stream"))?;
+        let audio = probed
+            .streams
+            .iter()
+            .find(|s| s.codec_type.as_deref() == Some("audio"));
+
+        let rotation = video
+            .side_data_list
+            .iter()
+            .find_map(|d| d.rotation)
+            // Display matrix rotation is counter-clockwise
+            .map(|r| -(r.round() as i32))
+            .or_else(|| video.tags.get("rotate").and_then(|r| r.parse().ok()))
+            .unwrap_or(0)
+            .rem_euclid(360);
+
+        Ok(MediaInfo {
+            container,
+            duration: format
+                .duration
+                .and_then(|d| d.parse::<f64>().ok())
+                .filter(|d| *d > 0.0),
+            video_codec: video.codec_name.clone().unwrap_or_default(),
+            audio_codec: audio.map(|a| a.codec_name.clone().unwrap_or_default()),
+            width: video.width.unwrap_or_default(),
+            height: video.height.unwrap_or_default(),
+            rotation,
+            frame_rate: video
+                .avg_frame_rate
+                .as_deref()
+                .and_then(parse_frame_rate)
+                .or_else(|| video.r_frame_rate.as_deref().and_then(parse_frame_rate)),
+            audio_channels: audio.and_then(|a| a.channels),
+            bit_rate: format.bit_rate.and_then(|b| b.parse().ok()),
+        })
+    }
+
+    /// Run ffmpeg with `-progress pipe:1`, reporting percent complete against `duration`.
+    /// On failure the error is the last lines ffmpeg wrote to stderr.
+    async fn run_ffmpeg(
+        args: &[String],
+        duration: Option<f64>,
+        on_progress: &impl Fn(f32),
+    ) -> Result<(), String> {
+        let mut child = Command::new("ffmpeg")
+            .args(["-hide_banner", "-nostats", "-loglevel", "error"])
+            .args(["-progress", "pipe:1"])
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
             .map_err(|e| {
-                error!("Thumbnail generation error: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed to generate thumbnail: {}", e),
-                )
+                error!("FFmpeg process error: {}", e);
+                format!("Failed to start ffmpeg: {}", e)
             })?;
 
-        if !thumbnail_result.success() {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Thumbnail generation failed".to_string(),
-            ));
+        // Drain stderr alongside stdout so ffmpeg never blocks on a full pipe
+        let stderr = child.stderr.take();
+        let stderr_tail = tokio::spawn(async move {
+            let mut tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+            if let Some(stderr) = stderr {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if tail.len() == STDERR_TAIL_LINES {
+                        tail.pop_front();
+                    }
+                    tail.push_back(line);
+                }
+            }
+            tail
+        });
+
+        if let Some(stdout) = child.stdout.take() {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                // Despite its name, out_time_ms is reported in microseconds
+                let out_time_us = line
+                    .strip_prefix("out_time_us=")
+                    .or_else(|| line.strip_prefix("out_time_ms="))
+                    .and_then(|v| v.trim().parse::<f64>().ok());
+
+                if let (Some(out_time_us), Some(duration)) = (out_time_us, duration) {
+                    let percent = (out_time_us / 1_000_000.0 / duration * 100.0) as f32;
+                    on_progress(percent.clamp(0.0, 100.0));
+                }
+            }
         }
 
-        // Get the base name without extension
-        let base_name = Path::new(filename)
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("video");
+        let status = child.wait().await.map_err(|e| {
+            error!("FFmpeg process error: {}", e);
+            format!("Failed to wait for ffmpeg: {}", e)
+        })?;
+        let tail = stderr_tail.await.unwrap_or_default();
 
-        // Create filenames for upload
-        let processed_filename = format!("{}-{}.mp4", base_name, video_id);
-        let thumbnail_filename = format!("{}-{}-thumbnail.jpg", base_name, video_id);
+        if !status.success() {
+            let detail = Vec::from(tail).join("\n");
+            error!("FFmpeg exited with {}: {}", status, detail);
+            return Err(if detail.is_empty() {
+                format!("ffmpeg exited with {}", status)
+            } else {
+                detail
+            });
+        }
 
-        info!(
-            "Video processed successfully: {} and thumbnail: {}",
-            processed_filename, thumbnail_filename
-        );
+        on_progress(100.0);
+        Ok(())
+    }
+}
 
-        // Read the processed video and thumbnail
-        let processed_video = fs::read(output_path).await.map_err(|e| {
-            error!("Failed to read processed video: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to read processed video".to_string(),
-            )
-        })?;
+fn path_arg(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
 
-        let thumbnail_data = fs::read(thumbnail_path).await.map_err(|e| {
-            error!("Failed to read thumbnail: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to read thumbnail".to_string(),
-            )
-        })?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Ok((processed_filename, thumbnail_filename))
+    #[test]
+    fn thumbnail_seek_stays_inside_short_clips() {
+        assert_eq!(thumbnail_seek(Some(60.0)), 1.0);
+        assert_eq!(thumbnail_seek(Some(2.0)), 1.0);
+        assert_eq!(thumbnail_seek(Some(0.5)), 0.25);
+        assert_eq!(thumbnail_seek(Some(0.0)), 0.0);
+        assert_eq!(thumbnail_seek(None), 0.0);
     }
 }
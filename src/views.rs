@@ -0,0 +1,337 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// How often expired entries are swept out
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Who is watching. Anonymous viewers are told apart by address and, when they
+/// send one, the device they claim.
+pub enum Viewer<'a> {
+    User(&'a str),
+    Anonymous {
+        ip: IpAddr,
+        device_id: Option<&'a str>,
+    },
+}
+
+impl Viewer<'_> {
+    fn key(&self) -> String {
+        match self {
+            Viewer::User(id) => format!("user:{}", id),
+            Viewer::Anonymous {
+                ip,
+                device_id: Some(device_id),
+            } => format!("ip:{}/device:{}", ip, device_id),
+            Viewer::Anonymous {
+                ip,
+                device_id: None,
+            } => format!("ip:{}", ip),
+        }
+    }
+}
+
+/// Remembers which viewers were recently counted for which videos, so replays and
+/// retries within the window don't inflate view counts. Kept in memory, so each
+/// instance deduplicates on its own.
+pub struct ViewTracker {
+    // Shortest watch, in seconds, that counts as a view
+    pub min_watch_secs: f64,
+    pub window: Duration,
+    // Most anonymous views of one video counted from one address per window, so
+    // a fresh `device_id` on every request can't inflate the count
+    pub max_anonymous_per_ip: usize,
+    seen: Mutex<Seen>,
+}
+
+/// A view `record` counted, which `forget` can take back
+pub struct CountedView {
+    id: u64,
+}
+
+struct Seen {
+    // `video_id` and viewer key to the view counted for them
+    views: HashMap<(String, String), (u64, Instant)>,
+    // `video_id` and address to the anonymous views counted from it
+    addresses: HashMap<(String, IpAddr), Vec<(u64, Instant)>>,
+    // Tells apart views counted at the same instant
+    next_id: u64,
+    last_pruned: Instant,
+}
+
+impl ViewTracker {
+    pub fn new(min_watch_secs: f64, window: Duration, max_anonymous_per_ip: usize) -> Self {
+        Self {
+            min_watch_secs,
+            window,
+            max_anonymous_per_ip,
+            seen: Mutex::new(Seen {
+                views: HashMap::new(),
+                addresses: HashMap::new(),
+                next_id: 0,
+                last_pruned: Instant::now(),
+            }),
+        }
+    }
+
+    /// Whether a watch of `watched_secs` is long enough to count. Videos shorter
+    /// than the threshold count once watched to the end.
+    pub fn meets_threshold(&self, watched_secs: f64, duration: Option<f64>) -> bool {
+        let required = match duration {
+            Some(duration) if duration > 0.0 => self.min_watch_secs.min(duration),
+            _ => self.min_watch_secs,
+        };
+        watched_secs >= required
+    }
+
+    /// Record a view, returning `None` when the viewer was already counted for
+    /// this video within the window, or their address has used up its views
+    pub fn record(&self, video_id: &str, viewer: &Viewer) -> Option<CountedView> {
+        self.record_at(video_id, viewer, Instant::now())
+    }
+
+    fn record_at(&self, video_id: &str, viewer: &Viewer, now: Instant) -> Option<CountedView> {
+        let Ok(mut seen) = self.seen.lock() else {
+            // A poisoned lock shouldn't stop views being counted
+            return Some(CountedView { id: u64::MAX });
+        };
+
+        let window = self.window;
+        let current = |at: &Instant| now.saturating_duration_since(*at) < window;
+        if now.saturating_duration_since(seen.last_pruned) >= PRUNE_INTERVAL {
+            seen.views.retain(|_, (_, at)| current(at));
+            seen.addresses.retain(|_, counted| {
+                counted.retain(|(_, at)| current(at));
+                !counted.is_empty()
+            });
+            seen.last_pruned = now;
+        }
+
+        let key = (video_id.to_string(), viewer.key());
+        if seen.views.get(&key).is_some_and(|(_, at)| current(at)) {
+            return None;
+        }
+
+        let id = seen.next_id;
+        seen.next_id += 1;
+        if let Viewer::Anonymous { ip, .. } = viewer {
+            let counted = seen
+                .addresses
+                .entry((video_id.to_string(), *ip))
+                .or_default();
+            counted.retain(|(_, at)| current(at));
+            if counted.len() >= self.max_anonymous_per_ip {
+                return None;
+            }
+            counted.push((id, now));
+        }
+
+        seen.views.insert(key, (id, now));
+        Some(CountedView { id })
+    }
+
+    /// Undo `record`, for when the view couldn't be saved and may be retried.
+    /// Views counted since, even from the same address, are left alone.
+    pub fn forget(&self, video_id: &str, viewer: &Viewer, view: CountedView) {
+        let Ok(mut seen) = self.seen.lock() else {
+            return;
+        };
+
+        let key = (video_id.to_string(), viewer.key());
+        if seen.views.get(&key).is_some_and(|(id, _)| *id == view.id) {
+            seen.views.remove(&key);
+        }
+        if let Viewer::Anonymous { ip, .. } = viewer
+            && let Some(counted) = seen.addresses.get_mut(&(video_id.to_string(), *ip))
+        {
+            counted.retain(|(id, _)| *id != view.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const VIDEO: &str = "video";
+    const WINDOW: Duration = Duration::from_secs(3600);
+    const HOME: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+
+    fn tracker() -> ViewTracker {
+        ViewTracker::new(10.0, WINDOW, 3)
+    }
+
+    fn device(id: &str) -> Viewer<'_> {
+        Viewer::Anonymous {
+            ip: HOME,
+            device_id: Some(id),
+        }
+    }
+
+    #[test]
+    fn repeat_view_within_window_is_ignored() {
+        let tracker = tracker();
+        let now = Instant::now();
+
+        assert!(
+            tracker
+                .record_at(VIDEO, &Viewer::User("alice"), now)
+                .is_some()
+        );
+        assert!(
+            tracker
+                .record_at(VIDEO, &Viewer::User("alice"), now + WINDOW / 2)
+                .is_none()
+        );
+        assert!(
+            tracker
+                .record_at("other", &Viewer::User("alice"), now)
+                .is_some()
+        );
+        assert!(
+            tracker
+                .record_at(VIDEO, &Viewer::User("bob"), now)
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn view_counts_again_after_window() {
+        let tracker = tracker();
+        let now = Instant::now();
+
+        assert!(
+            tracker
+                .record_at(VIDEO, &Viewer::User("alice"), now)
+                .is_some()
+        );
+        assert!(
+            tracker
+                .record_at(VIDEO, &Viewer::User("alice"), now + WINDOW)
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn anonymous_views_are_capped_per_address() {
+        let tracker = tracker();
+        let now = Instant::now();
+
+        for id in ["a", "b", "c"] {
+            assert!(tracker.record_at(VIDEO, &device(id), now).is_some());
+        }
+        assert!(tracker.record_at(VIDEO, &device("d"), now).is_none());
+        let no_device = Viewer::Anonymous {
+            ip: HOME,
+            device_id: None,
+        };
+        assert!(tracker.record_at(VIDEO, &no_device, now).is_none());
+
+        // Other videos, other addresses and signed-in users have their own allowance
+        assert!(tracker.record_at("other", &device("d"), now).is_some());
+        let elsewhere = Viewer::Anonymous {
+            ip: IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1)),
+            device_id: Some("d"),
+        };
+        assert!(tracker.record_at(VIDEO, &elsewhere, now).is_some());
+        assert!(
+            tracker
+                .record_at(VIDEO, &Viewer::User("alice"), now)
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn address_cap_resets_after_window() {
+        let tracker = tracker();
+        let now = Instant::now();
+
+        for id in ["a", "b", "c"] {
+            assert!(tracker.record_at(VIDEO, &device(id), now).is_some());
+        }
+        assert!(
+            tracker
+                .record_at(VIDEO, &device("d"), now + WINDOW)
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn forget_allows_a_retry() {
+        let tracker = tracker();
+        let now = Instant::now();
+
+        let view = tracker.record_at(VIDEO, &device("a"), now).unwrap();
+        tracker.forget(VIDEO, &device("a"), view);
+        assert!(tracker.record_at(VIDEO, &device("a"), now).is_some());
+    }
+
+    #[test]
+    fn forget_only_removes_its_own_view() {
+        let tracker = tracker();
+        let now = Instant::now();
+        let second = Duration::from_secs(1);
+
+        let first = tracker.record_at(VIDEO, &device("a"), now).unwrap();
+        assert!(
+            tracker
+                .record_at(VIDEO, &device("b"), now + second)
+                .is_some()
+        );
+        assert!(
+            tracker
+                .record_at(VIDEO, &device("c"), now + second * 2)
+                .is_some()
+        );
+        tracker.forget(VIDEO, &device("a"), first);
+        assert!(
+            tracker
+                .record_at(VIDEO, &device("b"), now + second * 2)
+                .is_none()
+        );
+
+        // Once `b` expires only `c` still counts against the address
+        let later = now + WINDOW + second + second / 2;
+        assert!(tracker.record_at(VIDEO, &device("d"), later).is_some());
+        assert!(tracker.record_at(VIDEO, &device("e"), later).is_some());
+        assert!(tracker.record_at(VIDEO, &device("f"), later).is_none());
+    }
+
+    #[test]
+    fn stale_forget_leaves_a_newer_view() {
+        let tracker = tracker();
+        let now = Instant::now();
+
+        let old = tracker
+            .record_at(VIDEO, &Viewer::User("alice"), now)
+            .unwrap();
+        assert!(
+            tracker
+                .record_at(VIDEO, &Viewer::User("alice"), now + WINDOW)
+                .is_some()
+        );
+        tracker.forget(VIDEO, &Viewer::User("alice"), old);
+
+        assert!(
+            tracker
+                .record_at(VIDEO, &Viewer::User("alice"), now + WINDOW)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn threshold_is_capped_by_duration() {
+        let tracker = tracker();
+
+        assert!(!tracker.meets_threshold(9.9, None));
+        assert!(tracker.meets_threshold(10.0, Some(60.0)));
+        assert!(tracker.meets_threshold(4.0, Some(4.0)));
+        assert!(!tracker.meets_threshold(3.0, Some(4.0)));
+        assert!(!tracker.meets_threshold(9.0, Some(0.0)));
+    }
+}